@@ -1,38 +1,61 @@
+use common::initial_market::{sector, AVAILABLE_STOCKS, INITIAL_PRICE, LOT_SIZE, MIN_QUANTITY, PRICE_BAND, TICK_SIZE};
+use common::order_book_layout::ORDER_BOOKS_KEY;
+use common::redis_storage::{MARKET_HALTED_UNTIL_KEY, ORDER_IDS_KEY, RESTORED_KEY, SEQUENCE_KEY};
 use redis::Commands;
 
-const AVAILABLE_STOCKS: &[&str] = &["AAPL","GOOGL","MSFT","AMZN","NVDA","META","TSLA","CRM","ORCL","IBM","CSCO","INTC","ADBE","QCOM","AVGO","JNJ","PFE","MRK","ABT","MRNA","LLY","GILD","BMY","AMGN","UNH","CI","MDT","TMO","BIIB","REGN","JPM","BAC","WFC","C","GS","MS","AXP","BLK","SCHW","V","MA","PYPL","BRK.B","MET","PRU","PG","KO","PEP","CL","UL","PM","GM","F","NKE","MDLZ","XOM","CVX","COP","BP","TTE","MPC","SLB","HAL","BKR","PSX","BA","CAT","GE","MMM","HON","UNP","LMT","RTX","DE","NOC","NEE","DUK","D","SO","CEG","DIS","CMCSA","VZ","T","NFLX"];
+mod snapshot;
+
 const REDIS_URL: &str = "redis://localhost:6379";
 
-fn main() {
+// setup                    reset every price to 100, set the sectors and reference data and clear the order books and order management system state
+// setup snapshot <file>    write the prices, sectors, reference data and order books to a file
+// setup restore <file>     put them back from a file written by snapshot
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    match args.iter().skip(1).map(String::as_str).collect::<Vec<&str>>()[..] {
+        [] | ["init"] => init(),
+        ["snapshot", path] => snapshot::snapshot(REDIS_URL, path).await,
+        ["restore", path] => snapshot::restore(REDIS_URL, path).await,
+        _ => {
+            eprintln!("Usage: setup [init | snapshot <file> | restore <file>]");
+            std::process::exit(2);
+        }
+    }
+}
+
+fn init() {
     let client = redis::Client::open(REDIS_URL).unwrap();
     let mut conn = client.get_connection().unwrap();
 
     // Set the stock price for each stock to initial value of 100.0000
     for (index, stock) in AVAILABLE_STOCKS.iter().enumerate() {
-        let _: () = conn.hset("stocks:prices", stock, 100.0000).unwrap();
-
-        // Top 15 stock is in the Technology sector, the 15 healthcare, 15 finance, 10 consumer_goods, 10 energy, 10 industrial, 5 utilities, 5 communication
-        let sector = match index {
-            0..=14 => "Technology",
-            15..=29 => "Healthcare",
-            30..=44 => "Finance",
-            45..=54 => "Consumer Goods",
-            55..=64 => "Energy",
-            65..=74 => "Industrial",
-            75..=79 => "Utilities",
-            80..=84 => "Communication",
-            _ => "Unknown",
-        };
-        
-        let _: () = conn.hset("stocks:sector", stock, sector).unwrap();
+        let _: () = conn.hset("stocks:prices", stock, INITIAL_PRICE).unwrap();
+        let _: () = conn.hset("stocks:sector", stock, sector(index)).unwrap();
+
+        // Every price must be a multiple of the tick size, every quantity a multiple of the lot size and at least the minimum quantity
+        let _: () = conn.hset("stocks:tick_size", stock, TICK_SIZE).unwrap();
+        let _: () = conn.hset("stocks:lot_size", stock, LOT_SIZE).unwrap();
+        let _: () = conn.hset("stocks:min_quantity", stock, MIN_QUANTITY).unwrap();
+        let _: () = conn.hset("stocks:price_band", stock, PRICE_BAND).unwrap();
     }
 
-    // Clear all hash of order_book:*
+    // Clear every order book, with the keys of all its orders (see common::order_book_layout)
+    // order_book:{stock} on its own is a book from before the sorted sets, cleared as well
     for stock in AVAILABLE_STOCKS {
-        let _: () = conn.del(format!("order_book:{}", stock)).unwrap();
+        let mut keys: Vec<String> = conn.scan_match(format!("order_book:{}:*", stock)).unwrap().collect();
+        keys.push(format!("order_book:{}", stock));
+        let _: () = conn.del(keys).unwrap();
     }
+    let _: () = conn.del(ORDER_BOOKS_KEY).unwrap();
+    // The order management system starts over too: no market-wide halt, sequence numbers from 1 and no order id taken
+    let _: () = conn.del(&[MARKET_HALTED_UNTIL_KEY, SEQUENCE_KEY, ORDER_IDS_KEY]).unwrap();
+    // The journal of the order book manager starts over from the cleared books when it starts again
+    let _: () = conn.set(RESTORED_KEY, 1).unwrap();
 
     println!("Stock prices initialized");
     println!("Stock sector initialized");
+    println!("Stock reference data initialized");
     println!("Order book cleared");
+    println!("Order management system state cleared");
 }
@@ -0,0 +1,32 @@
+use common::redis_storage::RedisStorage;
+use common::storage::{read_snapshot, read_snapshot_file, write_snapshot_file};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Write the prices, sectors, reference data and order books to the file
+pub async fn snapshot(redis_url: &str, path: &str) {
+    let storage = RedisStorage::connect(redis_url).await.expect("Failed to connect to Redis");
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+    let snapshot = read_snapshot(&storage, timestamp).await.expect("Failed to read the market state from Redis");
+    write_snapshot_file(path, &snapshot).expect("Failed to write snapshot file");
+
+    println!("Snapshot of {} stocks and {} order books written to {}", snapshot.prices.len(), snapshot.order_books.len(), path);
+}
+
+// Replace the market state in Redis with the one in the file
+// The order book manager keeps the books in memory and overwrites them, so it has to be stopped first and started again after
+pub async fn restore(redis_url: &str, path: &str) {
+    let snapshot = match read_snapshot_file(path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Failed to read snapshot file: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let storage = RedisStorage::connect(redis_url).await.expect("Failed to connect to Redis");
+    storage.restore(&snapshot).await.expect("Failed to restore the market state into Redis");
+
+    println!("Restored {} stocks and {} order books from {}", snapshot.prices.len(), snapshot.order_books.len(), path);
+    println!("Restart the order book manager so it loads the restored order books");
+}
@@ -0,0 +1,34 @@
+use crate::models::ReferenceData;
+use crate::price::Price;
+
+// The market as it starts, written to Redis by setup init and used by main --in-memory when there is no snapshot to start from
+
+pub const AVAILABLE_STOCKS: &[&str] = &["AAPL","GOOGL","MSFT","AMZN","NVDA","META","TSLA","CRM","ORCL","IBM","CSCO","INTC","ADBE","QCOM","AVGO","JNJ","PFE","MRK","ABT","MRNA","LLY","GILD","BMY","AMGN","UNH","CI","MDT","TMO","BIIB","REGN","JPM","BAC","WFC","C","GS","MS","AXP","BLK","SCHW","V","MA","PYPL","BRK.B","MET","PRU","PG","KO","PEP","CL","UL","PM","GM","F","NKE","MDLZ","XOM","CVX","COP","BP","TTE","MPC","SLB","HAL","BKR","PSX","BA","CAT","GE","MMM","HON","UNP","LMT","RTX","DE","NOC","NEE","DUK","D","SO","CEG","DIS","CMCSA","VZ","T","NFLX"];
+// Every stock starts at 100.0000
+pub const INITIAL_PRICE: f64 = 100.0;
+// Reference data, the same for every stock for now but kept per stock so it can differ
+pub const TICK_SIZE: f64 = 0.01;
+pub const LOT_SIZE: u32 = 5;
+pub const MIN_QUANTITY: u32 = 5;
+// Trading in a stock halts when its price moves 10% from its last auction price
+pub const PRICE_BAND: f64 = 0.1;
+
+// Sector of the stock at this index of AVAILABLE_STOCKS
+// Top 15 stock is in the Technology sector, the 15 healthcare, 15 finance, 10 consumer_goods, 10 energy, 10 industrial, 5 utilities, 5 communication
+pub fn sector(index: usize) -> &'static str {
+    match index {
+        0..=14 => "Technology",
+        15..=29 => "Healthcare",
+        30..=44 => "Finance",
+        45..=54 => "Consumer Goods",
+        55..=64 => "Energy",
+        65..=74 => "Industrial",
+        75..=79 => "Utilities",
+        80..=84 => "Communication",
+        _ => "Unknown",
+    }
+}
+
+pub fn reference_data() -> ReferenceData {
+    ReferenceData { tick_size: Price::from_f64(TICK_SIZE), lot_size: LOT_SIZE, min_quantity: MIN_QUANTITY, price_band: Some(PRICE_BAND) }
+}
\ No newline at end of file
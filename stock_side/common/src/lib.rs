@@ -1 +1,7 @@
-pub mod models;
\ No newline at end of file
+pub mod initial_market;
+pub mod memory_storage;
+pub mod models;
+pub mod order_book_layout;
+pub mod price;
+pub mod redis_storage;
+pub mod storage;
\ No newline at end of file
@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::models::{DepthLevel, Order, ReferenceData};
+use crate::order_book_layout::shown_quantity;
+use crate::price::Price;
+use crate::storage::{MarketSnapshot, RestingOrders, SavedOrderBook, Storage, StorageResult};
+
+// Storage that only lives as long as the process, for tests and for running the stock side without a Redis server
+// Clones share the same state
+#[derive(Clone)]
+pub struct MemoryStorage {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    prices: HashMap<String, Price>,
+    sectors: HashMap<String, String>,
+    reference_data: HashMap<String, ReferenceData>,
+    order_books: BTreeMap<String, SavedOrderBook>,
+    sequence: Option<u64>,
+    market_halted_until: u64,
+    order_ids: HashSet<String>,
+    restored: bool,
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStorage {
+    // Nothing in it comes from the journal, so it counts as restored
+    pub fn new() -> Self {
+        let state = MemoryState { restored: true, ..Default::default() };
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    // Start from a snapshot written by setup snapshot
+    // The orders still resting are known ids too, a snapshot from before the ids were kept doesn't have them
+    pub fn from_snapshot(snapshot: MarketSnapshot) -> Self {
+        let resting_ids = snapshot
+            .order_books
+            .iter()
+            .flat_map(|order_book| order_book.buy_orders.iter().chain(&order_book.sell_orders).chain(&order_book.stop_orders))
+            .map(|order| order.id.clone())
+            .collect::<Vec<String>>();
+        let state = MemoryState {
+            prices: snapshot.prices.into_iter().collect(),
+            sectors: snapshot.sectors.into_iter().collect(),
+            reference_data: snapshot.reference_data.into_iter().collect(),
+            order_books: snapshot
+                .order_books
+                .into_iter()
+                .map(|order_book| (order_book.stock_symbol.clone(), order_book))
+                .collect(),
+            sequence: snapshot.sequence,
+            market_halted_until: snapshot.market_halted_until,
+            order_ids: snapshot.order_ids.into_iter().chain(resting_ids).collect(),
+            restored: true,
+        };
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    pub fn set_sector(&self, stock_symbol: &str, sector: &str) {
+        self.state().sectors.insert(stock_symbol.to_string(), sector.to_string());
+    }
+
+    pub fn set_reference_data(&self, stock_symbol: &str, reference_data: ReferenceData) {
+        self.state().reference_data.insert(stock_symbol.to_string(), reference_data);
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().expect("Memory storage lock poisoned")
+    }
+}
+
+impl Storage for MemoryStorage {
+    async fn prices(&self) -> StorageResult<HashMap<String, Price>> {
+        Ok(self.state().prices.clone())
+    }
+
+    async fn price(&self, stock_symbol: &str) -> StorageResult<Option<Price>> {
+        Ok(self.state().prices.get(stock_symbol).copied())
+    }
+
+    async fn set_price(&self, stock_symbol: &str, price: Price) -> StorageResult<()> {
+        self.state().prices.insert(stock_symbol.to_string(), price);
+        Ok(())
+    }
+
+    async fn sectors(&self) -> StorageResult<HashMap<String, String>> {
+        Ok(self.state().sectors.clone())
+    }
+
+    async fn reference_data(&self) -> StorageResult<HashMap<String, ReferenceData>> {
+        Ok(self.state().reference_data.clone())
+    }
+
+    async fn order_books(&self) -> StorageResult<Vec<SavedOrderBook>> {
+        Ok(self.state().order_books.values().cloned().collect())
+    }
+
+    async fn resting_orders(&self) -> StorageResult<RestingOrders> {
+        let state = self.state();
+        let resting_orders = state
+            .order_books
+            .values()
+            .map(|order_book| (order_book.stock_symbol.clone(), (order_book.buy_orders.clone(), order_book.sell_orders.clone())))
+            .collect();
+        Ok(resting_orders)
+    }
+
+    async fn depth_levels(&self, stock_symbol: &str, levels: usize) -> StorageResult<(Vec<DepthLevel>, Vec<DepthLevel>)> {
+        let state = self.state();
+        let Some(order_book) = state.order_books.get(stock_symbol) else {
+            return Ok((Vec::new(), Vec::new()));
+        };
+        Ok((top_levels(&order_book.buy_orders, levels), top_levels(&order_book.sell_orders, levels)))
+    }
+
+    async fn load_order_books(&self) -> StorageResult<Vec<SavedOrderBook>> {
+        self.order_books().await
+    }
+
+    async fn save_order_books(&self, order_books: Vec<SavedOrderBook>, sequence: u64, market_halted_until: u64) -> StorageResult<()> {
+        let mut state = self.state();
+        for order_book in order_books {
+            state.order_books.insert(order_book.stock_symbol.clone(), order_book);
+        }
+        state.sequence = Some(sequence);
+        state.market_halted_until = market_halted_until;
+        Ok(())
+    }
+
+    async fn sequence(&self) -> StorageResult<Option<u64>> {
+        Ok(self.state().sequence)
+    }
+
+    async fn market_halted_until(&self) -> StorageResult<u64> {
+        Ok(self.state().market_halted_until)
+    }
+
+    async fn take_restored(&self) -> StorageResult<bool> {
+        Ok(std::mem::take(&mut self.state().restored))
+    }
+
+    async fn order_ids(&self) -> StorageResult<HashSet<String>> {
+        Ok(self.state().order_ids.clone())
+    }
+
+    async fn add_order_id(&self, order_id: &str) -> StorageResult<bool> {
+        Ok(self.state().order_ids.insert(order_id.to_string()))
+    }
+}
+
+// Top levels of one side in priority order, the way order_book_layout::read_levels adds them up in Redis
+fn top_levels(orders: &[Order], levels: usize) -> Vec<DepthLevel> {
+    let mut depth_levels: Vec<DepthLevel> = Vec::new();
+    for order in orders {
+        let full = depth_levels.len() == levels;
+        match depth_levels.last_mut() {
+            Some(level) if level.price == order.price => {
+                level.quantity += shown_quantity(order);
+                level.order_count += 1;
+            }
+            _ if full => break,
+            _ => depth_levels.push(DepthLevel { price: order.price, quantity: shown_quantity(order), order_count: 1 }),
+        }
+    }
+    depth_levels
+}
\ No newline at end of file
@@ -1,23 +1,133 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::price::Price;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Order {
     pub id: String,
     pub stock_symbol: String,
     pub order_type: OrderType,
+    // Trader or account the order belongs to, two orders of the same account never trade with each other
+    // Orders without one are never treated as a self-trade
+    #[serde(default)]
+    pub account_id: Option<String>,
+    // Limit when not given, so existing senders keep working
+    #[serde(default)]
+    pub kind: OrderKind,
     pub quantity: u32,
-    pub price: f64,
+    // Ignored for market and stop orders, so they can be sent without one
+    #[serde(default)]
+    pub price: Price,
+    // Price that triggers a stop or stop-limit order
+    // For a trailing stop order this is the current trigger level, maintained by the order management system
+    #[serde(default)]
+    pub stop_price: Option<Price>,
+    // Distance of a trailing stop order's trigger level from the best price seen since it was accepted
+    #[serde(default)]
+    pub trailing_offset: Option<TrailingOffset>,
+    // Post-only order: only ever adds liquidity, what to do if it would trade on arrival
+    #[serde(default)]
+    pub post_only: Option<PostOnly>,
+    // Pegged order: its price follows the best bid, best offer or midpoint of the order book
+    #[serde(default)]
+    pub peg: Option<Peg>,
+    // Iceberg order: only this much of the quantity is shown in the order book at a time, the rest is hidden
+    // None shows the whole quantity
+    #[serde(default)]
+    pub display_quantity: Option<u32>,
     pub timestamp: u64,
     pub partial_fill: bool,
+    // GTC when not given
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    // Unix timestamp (seconds) the order expires at, given by the sender for GTD, set by the order management system for DAY
+    #[serde(default)]
+    pub expire_time: Option<u64>,
+    // Assigned by the order management system when the order is accepted, used for time priority
+    #[serde(default)]
+    pub sequence: u64,
+    // Maintained by the order management system as the order fills, quantity above is what is left (leaves)
+    #[serde(default)]
+    pub filled_quantity: u32,
+    #[serde(default)]
+    pub average_price: Price,
+    // Maintained by the order management system for iceberg orders, what is left of the peak currently shown
+    #[serde(default)]
+    pub visible_quantity: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum OrderType {
     Buy,
     Sell,
 }
 
+// Limit: trade at the given price or better, the rest waits in the order book
+// Market: trade at whatever the order book offers, the rest is cancelled once the book runs dry
+// Stop: waits outside the order book until the last price reaches the stop price, then becomes a market order
+// StopLimit: same as stop, but becomes a limit order at price
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrderKind {
+    #[default]
+    Limit,
+    Market,
+    Stop,
+    StopLimit,
+    // Stop order whose stop price follows the market, becomes a market order once triggered
+    TrailingStop,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PostOnly {
+    // Cancel the order
+    Reject,
+    // Move the price one tick behind the best price of the opposite side
+    Reprice,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Peg {
+    pub reference: PegReference,
+    // Added to the reference price, negative to sit below it
+    pub offset: Price,
+}
+
+// Pegged orders are not part of the reference, only the other orders of the order book are
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PegReference {
+    BestBid,
+    BestOffer,
+    Midpoint,
+}
+
+// A sell trailing stop trails below the highest price, a buy trailing stop above the lowest price
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum TrailingOffset {
+    Amount(Price),
+    Percent(f64),
+}
+
+// How long an order stays in the order book
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeInForce {
+    // Good till cancel: rests until filled
+    #[default]
+    #[serde(rename = "GTC")]
+    GoodTillCancel,
+    // Expires at the session close of the day it was accepted
+    #[serde(rename = "DAY")]
+    Day,
+    // Immediate or cancel: whatever cannot be filled on arrival is cancelled
+    #[serde(rename = "IOC")]
+    ImmediateOrCancel,
+    // Fill or kill: filled entirely on arrival or not at all
+    #[serde(rename = "FOK")]
+    FillOrKill,
+    // Good till date: expires at expire_time
+    #[serde(rename = "GTD")]
+    GoodTillDate,
+}
 
 impl fmt::Display for OrderType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -28,18 +138,312 @@ impl fmt::Display for OrderType {
     }
 }
 
+impl fmt::Display for OrderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderKind::Limit => write!(f, "Limit"),
+            OrderKind::Market => write!(f, "Market"),
+            OrderKind::Stop => write!(f, "Stop"),
+            OrderKind::StopLimit => write!(f, "StopLimit"),
+            OrderKind::TrailingStop => write!(f, "TrailingStop"),
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::ZeroQuantity => write!(f, "Quantity must be above zero"),
+            RejectReason::InvalidPrice => write!(f, "Price must be a positive number"),
+            RejectReason::InvalidStopPrice => write!(f, "Stop price must be a positive number"),
+            RejectReason::PriceOutOfRange => write!(f, "Price is too far from the current stock price"),
+            RejectReason::UnknownSymbol => write!(f, "Unknown stock symbol"),
+            RejectReason::DuplicateOrderId => write!(f, "Order id already used"),
+            RejectReason::AlreadyExpired => write!(f, "Order already expired"),
+            RejectReason::MissingStopPrice => write!(f, "Stop order without stop price"),
+            RejectReason::MissingTrailingOffset => write!(f, "Trailing stop order without trailing offset"),
+            RejectReason::InvalidTrailingOffset => write!(f, "Trailing offset must be above zero and leave a positive stop price"),
+            RejectReason::NoLastPrice => write!(f, "No last price to trail from yet, send a stop price"),
+            RejectReason::InvalidDisplayQuantity => write!(f, "Display quantity must be above zero"),
+            RejectReason::InvalidTickSize => write!(f, "Price is not a multiple of the tick size"),
+            RejectReason::InvalidLotSize => write!(f, "Quantity is not a multiple of the lot size"),
+            RejectReason::BelowMinimumQuantity => write!(f, "Quantity is below the minimum quantity"),
+            RejectReason::IcebergAllOrNone => write!(f, "Iceberg order must allow partial fills"),
+            RejectReason::NotLimitOrder => write!(f, "Post-only and pegged orders must be limit orders"),
+            RejectReason::InvalidPegOffset => write!(f, "Peg offset is too far from the current stock price"),
+            RejectReason::OrderNotFound => write!(f, "Order is not resting in the order book"),
+            RejectReason::NotAllowedInAuction => write!(f, "Market, immediate or cancel and fill or kill orders are not accepted during a call auction"),
+            RejectReason::MissingExpireTime => write!(f, "Good till date order without expire time"),
+            RejectReason::MarketClosed => write!(f, "Market, immediate or cancel and fill or kill orders are not accepted while the market is closed"),
+            RejectReason::StorageUnavailable => write!(f, "Order management system storage is unavailable, send the request again"),
+        }
+    }
+}
+
+impl fmt::Display for TimeInForce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeInForce::GoodTillCancel => write!(f, "GTC"),
+            TimeInForce::Day => write!(f, "DAY"),
+            TimeInForce::ImmediateOrCancel => write!(f, "IOC"),
+            TimeInForce::FillOrKill => write!(f, "FOK"),
+            TimeInForce::GoodTillDate => write!(f, "GTD"),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Trade {
     pub buy_order_id: String,
     pub sell_order_id: String,
     pub stock_symbol: String,
     pub quantity: u32,
-    pub price: f64,
+    pub price: Price,
     pub timestamp: u64,
+    pub buy_sequence: u64,
+    pub sell_sequence: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Stock {
     pub symbol: String,
-    pub price: f64,
+    pub price: Price,
+}
+
+// Trading rules of a stock, set by the setup binary in stocks:tick_size, stocks:lot_size, stocks:min_quantity and stocks:price_band
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ReferenceData {
+    // Every price must be a multiple of it
+    pub tick_size: Price,
+    // Every quantity must be a multiple of it
+    pub lot_size: u32,
+    pub min_quantity: u32,
+    // How far the price may move from its reference price before trading is halted, 0.1 = 10% either side
+    // None to use the default band of the order book manager
+    #[serde(default)]
+    pub price_band: Option<f64>,
+}
+
+// Published when a resting DAY or GTD order is removed from the order book because it expired
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderExpired {
+    pub order_id: String,
+    pub stock_symbol: String,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    pub unfilled_quantity: u32,
+    pub expire_time: u64,
+    pub timestamp: u64,
+}
+
+// Everything that can be sent to the order management system on the order topic
+// A bare Order payload (without the enum tag) is still accepted as a new order
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum OrderRequest {
+    New(Order),
+    Cancel(CancelRequest),
+    Replace(ReplaceRequest),
+    DepthSnapshot(DepthSnapshotRequest),
+}
+
+// Remove a resting order from the order book
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CancelRequest {
+    pub id: String,
+    pub order_id: String,
+    pub stock_symbol: String,
+    pub timestamp: u64,
+}
+
+// Cancel/replace a resting order with a new quantity and price
+// Reducing the quantity at the same price keeps the time priority, anything else re-enters the book with a new sequence
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReplaceRequest {
+    pub id: String,
+    pub order_id: String,
+    pub stock_symbol: String,
+    pub quantity: u32,   // New open quantity of the order
+    pub price: Price,
+    pub timestamp: u64,
+}
+
+// Sent back to the requester of a cancel or replace
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderRequestAck {
+    pub request_id: String,
+    pub order_id: String,
+    pub status: AckStatus,
+    pub reason: Option<String>,   // Only for rejects
+    #[serde(default)]
+    pub reject_reason: Option<RejectReason>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AckStatus {
+    Accepted,
+    Rejected,
+}
+
+// Why the order management system rejected an order or a cancel/replace request
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    ZeroQuantity,
+    // Not a number, infinite, zero or negative
+    InvalidPrice,
+    InvalidStopPrice,
+    // Too far from the current price of the stock in stocks:prices
+    PriceOutOfRange,
+    // Not in stocks:prices
+    UnknownSymbol,
+    DuplicateOrderId,
+    AlreadyExpired,
+    MissingStopPrice,
+    MissingTrailingOffset,
+    InvalidTrailingOffset,
+    // A trailing stop order needs a last price or a stop price to start from
+    NoLastPrice,
+    InvalidDisplayQuantity,
+    // Price or stop price not a multiple of the tick size of the stock
+    InvalidTickSize,
+    // Quantity or display quantity not a multiple of the lot size of the stock
+    InvalidLotSize,
+    BelowMinimumQuantity,
+    IcebergAllOrNone,
+    // Post-only and pegged orders must be limit orders
+    NotLimitOrder,
+    // Peg offset further from the current price of the stock than a price may be
+    InvalidPegOffset,
+    // Cancel or replace of an order that is not resting in the order book
+    OrderNotFound,
+    // Market, IOC and FOK orders can't wait for the uncross of a call auction
+    NotAllowedInAuction,
+    // Market, IOC and FOK orders can't wait for the market to open
+    MarketClosed,
+    // GTD order without an expire time
+    MissingExpireTime,
+    // The storage could not be read or written, nothing happened to the order and the request can be sent again
+    StorageUnavailable,
+}
+
+// Order lifecycle event sent to the owner of the order
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExecutionReport {
+    pub order_id: String,
+    pub stock_symbol: String,
+    pub order_type: OrderType,
+    pub exec_type: ExecType,
+    #[serde(default)]
+    pub price: Price,   // Limit price of the order, changes when a post-only or pegged order is repriced
+    pub last_quantity: u32,   // Quantity of this fill, 0 if the report is not a fill
+    pub last_price: Price,   // Price of this fill, 0 if the report is not a fill
+    pub leaves_quantity: u32,   // Quantity still open in the order book
+    pub cumulative_quantity: u32,   // Quantity filled so far
+    pub average_price: Price,   // Average price of everything filled so far
+    #[serde(default)]
+    pub stop_price: Option<Price>,   // Stop price of a stop order, the current trigger level for a trailing stop order
+    pub reason: Option<String>,   // Why the order was cancelled or rejected
+    #[serde(default)]
+    pub reject_reason: Option<RejectReason>,   // Only for rejects, same as reason but typed for the sender to act on
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ExecType {
+    New,
+    Triggered,
+    // Trigger level of a trailing stop order moved, or a post-only or pegged order got a new price
+    Restated,
+    PartialFill,
+    Fill,
+    Cancelled,
+    Replaced,
+    Rejected,
+    Expired,
+}
+
+// Ask for a depth snapshot of a stock, e.g. a late joiner of the depth topic resynchronising
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DepthSnapshotRequest {
+    pub stock_symbol: String,
+}
+
+// Everything published on the depth topic, keyed by stock symbol
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum DepthMessage {
+    Snapshot(DepthSnapshot),
+    Update(DepthUpdate),
+}
+
+// Aggregated quantity of one price level
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DepthLevel {
+    pub price: Price,
+    pub quantity: u32,
+    pub order_count: u32,
+}
+
+// Top levels of both sides of the order book
+// sequence is the depth sequence of the book, apply the updates with a higher sequence on top of the snapshot
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DepthSnapshot {
+    pub stock_symbol: String,
+    pub bids: Vec<DepthLevel>,   // Highest price first
+    pub asks: Vec<DepthLevel>,   // Lowest price first
+    pub sequence: u64,
+    pub timestamp: u64,
+}
+
+// Levels of the top of the book that changed, sequence goes up by one for every update of the same stock
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DepthUpdate {
+    pub stock_symbol: String,
+    pub changes: Vec<DepthChange>,
+    pub sequence: u64,
+    pub timestamp: u64,
+}
+
+// New state of one level, quantity 0 means the level is gone (or no longer in the top levels)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DepthChange {
+    pub side: OrderType,
+    pub price: Price,
+    pub quantity: u32,
+    pub order_count: u32,
+}
+
+// Trading phase of an order book
+// During the pre-open and pre-close call auctions orders only accumulate, they are uncrossed at a single price when the call ends
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SessionPhase {
+    PreOpen,
+    #[default]
+    Continuous,
+    PreClose,
+    // Halted by a circuit breaker, orders accumulate like in a call and are uncrossed by the reopening auction after the cool-down
+    Halted,
+    // Outside the trading hours, limit orders wait in the book for the opening auction and nothing trades
+    Closed,
+}
+
+// Indicative result of a call auction if it was uncrossed right now, published while the call is running
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuctionInfo {
+    pub stock_symbol: String,
+    pub phase: SessionPhase,
+    pub indicative_price: Option<Price>,   // None if nothing would trade
+    pub matched_quantity: u32,   // Quantity that would trade at the indicative price
+    pub imbalance_quantity: u32,   // Quantity left over on the side with more interest at the indicative price
+    pub imbalance_side: Option<OrderType>,   // None if both sides match exactly
+    pub timestamp: u64,
+}
+
+// Published every time the trading phase of an order book changes, e.g. when a circuit breaker halts it and when it reopens
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TradingStatus {
+    pub stock_symbol: String,
+    pub phase: SessionPhase,
+    pub reason: Option<String>,   // Why trading was halted
+    pub resume_time: Option<u64>,   // When a halted order book reopens with an auction
+    pub timestamp: u64,
 }
\ No newline at end of file
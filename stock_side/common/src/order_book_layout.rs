@@ -0,0 +1,232 @@
+use redis::aio::ConnectionLike;
+use redis::{Pipeline, RedisResult};
+use std::collections::BTreeSet;
+
+use crate::models::{DepthLevel, Order};
+use crate::price::Price;
+
+// How the order books are kept in Redis, shared by the order management system (the only writer), the market data generator and setup
+//
+//   order_books                        set of the stock symbols that have an order book
+//   order_book:{symbol}:bids           sorted set of the resting buy orders, best first
+//   order_book:{symbol}:asks           sorted set of the resting sell orders, best first
+//   order_book:{symbol}:stops          sorted set of the stop orders waiting for their trigger
+//   order_book:{symbol}:order:{id}     hash of one order: its price, shown quantity and the whole order as JSON
+//   order_book:{symbol}:halted_until   when the halt of the book ends, only there while it is halted
+//
+// The score of a resting order is its price in 0.0001 (negative for bids) so ZRANGE from 0 starts at the best price on both sides
+// The member is "{priority}:{order id}" with the priority zero-padded, so orders at the same price sort in time priority
+// A reader can take the top of book or a few levels from the sorted sets and the small fields of the hashes, without the JSON of every order
+// The reads are Lua scripts, a script runs on its own in Redis so it never sees half of a save
+
+pub const ORDER_BOOKS_KEY: &str = "order_books";
+pub const PRICE_FIELD: &str = "price";
+pub const SHOWN_QUANTITY_FIELD: &str = "shown_quantity";
+pub const ORDER_FIELD: &str = "order";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BookSide {
+    Bids,
+    Asks,
+    Stops,
+}
+
+pub fn side_key(stock_symbol: &str, side: BookSide) -> String {
+    let side = match side {
+        BookSide::Bids => "bids",
+        BookSide::Asks => "asks",
+        BookSide::Stops => "stops",
+    };
+    format!("order_book:{}:{}", stock_symbol, side)
+}
+
+pub fn order_key(stock_symbol: &str, order_id: &str) -> String {
+    format!("order_book:{}:order:{}", stock_symbol, order_id)
+}
+
+pub fn halted_until_key(stock_symbol: &str) -> String {
+    format!("order_book:{}:halted_until", stock_symbol)
+}
+
+// Stop orders all have the same score, they are only kept in the order they were saved
+pub fn score(side: BookSide, price: Price) -> f64 {
+    match side {
+        BookSide::Bids => -price.0 as f64,
+        BookSide::Asks => price.0 as f64,
+        BookSide::Stops => 0.0,
+    }
+}
+
+// Price of a resting order back from its score
+pub fn score_price(score: f64) -> Price {
+    Price(score.abs() as i64)
+}
+
+pub fn member(priority: u64, order_id: &str) -> String {
+    format!("{:020}:{}", priority, order_id)
+}
+
+// Priority and order id of a member, None if it isn't one
+pub fn parse_member(member: &str) -> Option<(u64, &str)> {
+    let (priority, order_id) = member.split_once(':')?;
+    Some((priority.parse().ok()?, order_id))
+}
+
+// Quantity of the order shown in the order book, only the peak for an iceberg order
+pub fn shown_quantity(order: &Order) -> u32 {
+    match order.display_quantity {
+        Some(_) => order.visible_quantity,
+        None => order.quantity,
+    }
+}
+
+// Add or overwrite one order, a member it had before under another priority has to be removed separately
+pub fn add_order(pipe: &mut Pipeline, side: BookSide, priority: u64, order: &Order) {
+    let order_string = serde_json::to_string(order).expect("Failed to serialize order");
+    let price = match side {
+        BookSide::Stops => order.stop_price.unwrap_or(order.price),
+        BookSide::Bids | BookSide::Asks => order.price,
+    };
+
+    pipe.hset_multiple(
+        order_key(&order.stock_symbol, &order.id),
+        &[(PRICE_FIELD, price.to_string()), (SHOWN_QUANTITY_FIELD, shown_quantity(order).to_string()), (ORDER_FIELD, order_string)],
+    )
+    .ignore()
+    .zadd(side_key(&order.stock_symbol, side), member(priority, &order.id), score(side, price))
+    .ignore()
+    .sadd(ORDER_BOOKS_KEY, &order.stock_symbol)
+    .ignore();
+}
+
+pub fn remove_member(pipe: &mut Pipeline, stock_symbol: &str, side: BookSide, member: &str) {
+    pipe.zrem(side_key(stock_symbol, side), member).ignore();
+}
+
+pub fn remove_order(pipe: &mut Pipeline, stock_symbol: &str, order_id: &str) {
+    pipe.del(order_key(stock_symbol, order_id)).ignore();
+}
+
+// Stock symbols with an order book, sorted
+pub async fn read_stock_symbols<C: ConnectionLike>(conn: &mut C) -> RedisResult<Vec<String>> {
+    let stock_symbols: BTreeSet<String> = redis::cmd("SMEMBERS").arg(ORDER_BOOKS_KEY).query_async(conn).await?;
+    Ok(stock_symbols.into_iter().collect())
+}
+
+// Whole orders of the given sides in priority order with their priority, count None for all of them
+// Read by a script, so every side is from the same moment (the order management system saves a book in one MULTI/EXEC)
+pub async fn read_prioritized_orders<C: ConnectionLike>(
+    conn: &mut C,
+    stock_symbol: &str,
+    sides: &[BookSide],
+    count: Option<usize>,
+) -> RedisResult<Vec<Vec<(u64, Order)>>> {
+    if count == Some(0) {
+        return Ok(vec![Vec::new(); sides.len()]);
+    }
+    let stop = count.map_or(-1, |count| count as isize - 1);
+    let mut cmd = redis::cmd("EVAL");
+    cmd.arg(READ_ORDERS_SCRIPT).arg(sides.len());
+    for side in sides {
+        cmd.arg(side_key(stock_symbol, *side));
+    }
+    cmd.arg(stop).arg(order_key(stock_symbol, ""));
+
+    let sides: Vec<Vec<String>> = cmd.query_async(conn).await?;
+    let orders = sides
+        .into_iter()
+        .map(|side| {
+            side.chunks(2)
+                .map(|pair| {
+                    let (priority, _) = parse_member(&pair[0]).unwrap_or((0, ""));
+                    let order: Order = serde_json::from_str(&pair[1]).expect("Failed to deserialize order");
+                    (priority, order)
+                })
+                .collect()
+        })
+        .collect();
+    Ok(orders)
+}
+
+// Whole orders of the given sides in priority order, count None for all of them
+pub async fn read_orders<C: ConnectionLike>(
+    conn: &mut C,
+    stock_symbol: &str,
+    sides: &[BookSide],
+    count: Option<usize>,
+) -> RedisResult<Vec<Vec<Order>>> {
+    let sides = read_prioritized_orders(conn, stock_symbol, sides, count).await?;
+    Ok(sides.into_iter().map(|side| side.into_iter().map(|(_, order)| order).collect()).collect())
+}
+
+// Top levels of the bids or asks like in a depth snapshot (hidden quantity of iceberg orders left out)
+// Only the scores and shown quantities are read, the script stops at the first price after the last level
+pub async fn read_levels<C: ConnectionLike>(conn: &mut C, stock_symbol: &str, side: BookSide, levels: usize) -> RedisResult<Vec<DepthLevel>> {
+    let scored: Vec<String> = redis::cmd("EVAL")
+        .arg(READ_LEVELS_SCRIPT)
+        .arg(1)
+        .arg(side_key(stock_symbol, side))
+        .arg(levels)
+        .arg(order_key(stock_symbol, ""))
+        .query_async(conn)
+        .await?;
+
+    let mut depth_levels: Vec<DepthLevel> = Vec::new();
+    for pair in scored.chunks(2) {
+        let price = score_price(pair[0].parse().unwrap_or(0.0));
+        let shown_quantity: u32 = pair[1].parse().unwrap_or(0);
+        match depth_levels.last_mut() {
+            Some(level) if level.price == price => {
+                level.quantity += shown_quantity;
+                level.order_count += 1;
+            }
+            _ => depth_levels.push(DepthLevel { price, quantity: shown_quantity, order_count: 1 }),
+        }
+    }
+
+    Ok(depth_levels)
+}
+
+// KEYS: the sorted set of every side, ARGV: last index to read (-1 for all), prefix of the order hashes
+// Returns one array per side of member, order JSON, member, order JSON...
+const READ_ORDERS_SCRIPT: &str = r#"
+local sides = {}
+for i, side_key in ipairs(KEYS) do
+    local orders = {}
+    for _, member in ipairs(redis.call('ZRANGE', side_key, 0, ARGV[1])) do
+        local order = redis.call('HGET', ARGV[2] .. string.match(member, '^%d+:(.*)$'), 'order')
+        if order then
+            table.insert(orders, member)
+            table.insert(orders, order)
+        end
+    end
+    sides[i] = orders
+end
+return sides
+"#;
+
+// KEYS: the sorted set of one side, ARGV: number of levels, prefix of the order hashes
+// Returns score, shown quantity, score, shown quantity... of every order in the top levels
+const READ_LEVELS_SCRIPT: &str = r#"
+local levels, prefix = tonumber(ARGV[1]), ARGV[2]
+local result, count, last, start = {}, 0, nil, 0
+while true do
+    local batch = redis.call('ZRANGE', KEYS[1], start, start + 63, 'WITHSCORES')
+    if #batch == 0 then
+        return result
+    end
+    for i = 1, #batch, 2 do
+        if batch[i + 1] ~= last then
+            count = count + 1
+            if count > levels then
+                return result
+            end
+            last = batch[i + 1]
+        end
+        local shown_quantity = redis.call('HGET', prefix .. string.match(batch[i], '^%d+:(.*)$'), 'shown_quantity')
+        table.insert(result, batch[i + 1])
+        table.insert(result, shown_quantity or '0')
+    end
+    start = start + 64
+end
+"#;
\ No newline at end of file
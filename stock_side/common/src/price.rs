@@ -0,0 +1,237 @@
+use redis::{ErrorKind, FromRedisValue, RedisError, RedisResult, RedisWrite, ToRedisArgs, Value};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+
+// Number of decimals a price has, the market data generator always rounded to 4 decimals
+pub const DECIMALS: usize = 4;
+// Number of price units in 1.0
+pub const SCALE: i64 = 10_i64.pow(DECIMALS as u32);
+
+// Fixed-point price, a whole number of 0.0001 so prices compare, sort and add up exactly
+// Written as a decimal string ("113.0100") in JSON and Redis so nothing is lost on the way
+// JSON numbers are still accepted when reading, for senders that don't know about it (rounded to 4 decimals)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Price(pub i64);
+
+impl Price {
+    pub const ZERO: Price = Price(0);
+
+    // Rounded to the nearest 0.0001, only for prices that come out of f64 calculations (e.g. the market data generator algorithms)
+    pub fn from_f64(value: f64) -> Self {
+        Price((value * SCALE as f64).round() as i64)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+
+    // None on overflow, for prices built from what a sender chose (e.g. a trailing offset)
+    pub fn checked_add(self, other: Price) -> Option<Price> {
+        self.0.checked_add(other.0).map(Price)
+    }
+
+    pub fn checked_sub(self, other: Price) -> Option<Price> {
+        self.0.checked_sub(other.0).map(Price)
+    }
+}
+
+impl Add for Price {
+    type Output = Price;
+
+    fn add(self, other: Price) -> Price {
+        Price(self.0 + other.0)
+    }
+}
+
+impl Sub for Price {
+    type Output = Price;
+
+    fn sub(self, other: Price) -> Price {
+        Price(self.0 - other.0)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let scale = SCALE as u64;
+        write!(f, "{}{}.{:0width$}", sign, units / scale, units % scale, width = DECIMALS)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePriceError(String);
+
+impl fmt::Display for ParsePriceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid price: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParsePriceError {}
+
+// Exact, a price with more than 4 decimals is an error instead of being rounded
+impl FromStr for Price {
+    type Err = ParsePriceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParsePriceError(s.to_string());
+
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) || fraction.len() > DECIMALS {
+            return Err(invalid());
+        }
+
+        let whole: i64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| invalid())? };
+        let fraction: i64 = format!("{:0<width$}", fraction, width = DECIMALS).parse().map_err(|_| invalid())?;
+
+        let units = whole.checked_mul(SCALE).and_then(|units| units.checked_add(fraction)).ok_or_else(invalid)?;
+        Ok(Price(if negative { -units } else { units }))
+    }
+}
+
+impl Serialize for Price {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(PriceVisitor)
+    }
+}
+
+struct PriceVisitor;
+
+impl Visitor<'_> for PriceVisitor {
+    type Value = Price;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a decimal string or a number")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Price, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Price, E> {
+        if !value.is_finite() {
+            return Err(E::custom(format!("Invalid price: {}", value)));
+        }
+        Ok(Price::from_f64(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Price, E> {
+        value.checked_mul(SCALE).map(Price).ok_or_else(|| E::custom(format!("Invalid price: {}", value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Price, E> {
+        let value = i64::try_from(value).map_err(|_| E::custom(format!("Invalid price: {}", value)))?;
+        self.visit_i64(value)
+    }
+}
+
+impl ToRedisArgs for Price {
+    fn write_redis_args<W: ?Sized + RedisWrite>(&self, out: &mut W) {
+        out.write_arg(self.to_string().as_bytes())
+    }
+}
+
+// Prices written as f64 before (e.g. "100" by setup or "100.12345") are still read, rounded to 4 decimals
+impl FromRedisValue for Price {
+    fn from_redis_value(v: &Value) -> RedisResult<Self> {
+        let value = String::from_redis_value(v)?;
+        if let Ok(price) = value.parse::<Price>() {
+            return Ok(price);
+        }
+
+        match value.parse::<f64>() {
+            Ok(price) if price.is_finite() => Ok(Price::from_f64(price)),
+            _ => Err(RedisError::from((ErrorKind::TypeError, "Invalid price", value))),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimals_exactly() {
+        assert_eq!("113.01".parse(), Ok(Price(1_130_100)));
+        assert_eq!("113.0100".parse(), Ok(Price(1_130_100)));
+        assert_eq!("0.0001".parse(), Ok(Price(1)));
+        assert_eq!("100".parse(), Ok(Price(1_000_000)));
+        assert_eq!(".5".parse(), Ok(Price(5_000)));
+        // 0.1 + 0.2 is not 0.3 in f64, it is here
+        assert_eq!("0.1".parse::<Price>().unwrap() + "0.2".parse::<Price>().unwrap(), "0.3".parse::<Price>().unwrap());
+    }
+
+    #[test]
+    fn rejects_more_than_4_decimals() {
+        assert!("1.00001".parse::<Price>().is_err());
+        assert!("1.23456".parse::<Price>().is_err());
+    }
+
+    #[test]
+    fn parses_negatives() {
+        assert_eq!("-1.5".parse(), Ok(Price(-15_000)));
+        assert_eq!("-0.0001".parse(), Ok(Price(-1)));
+        assert!("--1".parse::<Price>().is_err());
+        assert!("-".parse::<Price>().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_and_overflowing_prices() {
+        for invalid in ["", ".", "abc", "1.2.3", "+1", "1e3", " 1", "1,5"] {
+            assert!(invalid.parse::<Price>().is_err(), "{:?} parsed", invalid);
+        }
+
+        // i64::MAX is 922337203685477.5807 in 0.0001
+        assert_eq!("922337203685477.5807".parse(), Ok(Price(i64::MAX)));
+        assert!("922337203685477.5808".parse::<Price>().is_err());
+        assert!("99999999999999999999".parse::<Price>().is_err());
+    }
+
+    #[test]
+    fn checked_arithmetic_catches_overflow() {
+        assert_eq!(Price(1).checked_add(Price(2)), Some(Price(3)));
+        assert_eq!(Price(i64::MAX - 10).checked_add(Price(1_000_000)), None);
+        assert_eq!(Price(i64::MIN + 10).checked_sub(Price(1_000_000)), None);
+    }
+
+    #[test]
+    fn deserializes_strings_exactly_and_numbers_rounded() {
+        assert_eq!(serde_json::from_str::<Price>("\"113.01\"").unwrap(), Price(1_130_100));
+        assert!(serde_json::from_str::<Price>("\"1.23456\"").is_err());
+
+        assert_eq!(serde_json::from_str::<Price>("113.01").unwrap(), Price(1_130_100));
+        assert_eq!(serde_json::from_str::<Price>("1.23456").unwrap(), Price(12_346));
+        assert_eq!(serde_json::from_str::<Price>("100").unwrap(), Price(1_000_000));
+        assert_eq!(serde_json::from_str::<Price>("-2").unwrap(), Price(-20_000));
+        assert!(serde_json::from_str::<Price>("18446744073709551615").is_err());
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        for price in [Price(0), Price(1), Price(1_130_100), Price(-15_000), Price(-1), Price(i64::MAX), Price(i64::MIN + 1)] {
+            assert_eq!(price.to_string().parse(), Ok(price));
+            assert_eq!(serde_json::from_str::<Price>(&serde_json::to_string(&price).unwrap()).unwrap(), price);
+        }
+        assert_eq!(Price(1_130_100).to_string(), "113.0100");
+        assert_eq!(Price(-1).to_string(), "-0.0001");
+        assert_eq!(serde_json::to_string(&Price(1_130_100)).unwrap(), "\"113.0100\"");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,464 @@
+use redis::{aio, AsyncCommands, Pipeline};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::models::{DepthLevel, Order, ReferenceData};
+use crate::order_book_layout::{
+    add_order, halted_until_key, member, read_levels, read_orders, read_prioritized_orders, read_stock_symbols, remove_member, remove_order,
+    score, BookSide, ORDER_BOOKS_KEY,
+};
+use crate::price::Price;
+use crate::storage::{MarketSnapshot, RestingOrders, SavedOrderBook, Storage, StorageError, StorageResult};
+
+const BOOK_SIDES: [BookSide; 3] = [BookSide::Bids, BookSide::Asks, BookSide::Stops];
+
+// Current price of every known stock, kept by the market data generator
+const STOCK_PRICES_KEY: &str = "stocks:prices";
+const STOCK_SECTOR_KEY: &str = "stocks:sector";
+// Reference data of every stock, set by the setup binary
+const TICK_SIZE_KEY: &str = "stocks:tick_size";
+const LOT_SIZE_KEY: &str = "stocks:lot_size";
+const MIN_QUANTITY_KEY: &str = "stocks:min_quantity";
+const PRICE_BAND_KEY: &str = "stocks:price_band";
+// Last sequence number assigned to an accepted order
+pub const SEQUENCE_KEY: &str = "oms:sequence";
+// End of the last market-wide halt, saved with the sequence number
+pub const MARKET_HALTED_UNTIL_KEY: &str = "oms:market_halted_until";
+// Set of every order id ever accepted, to reject duplicates
+pub const ORDER_IDS_KEY: &str = "oms:order_ids";
+// Set by setup init and restore, taken by the order book manager when it starts (see Storage::take_restored)
+pub const RESTORED_KEY: &str = "oms:restored";
+
+// Storage in Redis, the order books in the sorted-set layout of common::order_book_layout
+// Clones share the connection and what was last written of every order book
+#[derive(Clone)]
+pub struct RedisStorage {
+    conn: aio::MultiplexedConnection,
+    store: Arc<Mutex<OrderBookStore>>,
+}
+
+impl RedisStorage {
+    pub async fn connect(redis_url: &str) -> StorageResult<Self> {
+        println!("RedisStorage: Connecting to Redis: {}", redis_url);
+        let client = redis::Client::open(redis_url)?;
+        let conn = client.get_multiplexed_async_connection().await?;
+        println!("RedisStorage: Connected to Redis");
+
+        Ok(Self { conn, store: Arc::new(Mutex::new(OrderBookStore::new())) })
+    }
+
+    // Replace everything in Redis with the snapshot in one MULTI/EXEC, a reader sees either the old market or the restored one
+    // The order book manager keeps the books in memory and would overwrite them, it has to be stopped first and started again after
+    pub async fn restore(&self, snapshot: &MarketSnapshot) -> StorageResult<()> {
+        let mut conn = self.conn.clone();
+        let order_book_keys = scan_keys(&mut conn, "order_book:*").await?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        let stock_keys = [
+            STOCK_PRICES_KEY, STOCK_SECTOR_KEY, TICK_SIZE_KEY, LOT_SIZE_KEY, MIN_QUANTITY_KEY, PRICE_BAND_KEY, SEQUENCE_KEY, MARKET_HALTED_UNTIL_KEY,
+            ORDER_IDS_KEY, ORDER_BOOKS_KEY,
+        ];
+        pipe.del(&stock_keys).ignore();
+        if !order_book_keys.is_empty() {
+            pipe.del(&order_book_keys).ignore();
+        }
+
+        for (stock_symbol, price) in &snapshot.prices {
+            pipe.hset(STOCK_PRICES_KEY, stock_symbol, price).ignore();
+        }
+        for (stock_symbol, sector) in &snapshot.sectors {
+            pipe.hset(STOCK_SECTOR_KEY, stock_symbol, sector).ignore();
+        }
+        for (stock_symbol, reference_data) in &snapshot.reference_data {
+            pipe.hset(TICK_SIZE_KEY, stock_symbol, reference_data.tick_size).ignore();
+            pipe.hset(LOT_SIZE_KEY, stock_symbol, reference_data.lot_size).ignore();
+            pipe.hset(MIN_QUANTITY_KEY, stock_symbol, reference_data.min_quantity).ignore();
+            if let Some(price_band) = reference_data.price_band {
+                pipe.hset(PRICE_BAND_KEY, stock_symbol, price_band).ignore();
+            }
+        }
+        if let Some(sequence) = snapshot.sequence {
+            pipe.set(SEQUENCE_KEY, sequence).ignore();
+        }
+        if snapshot.market_halted_until > 0 {
+            pipe.set(MARKET_HALTED_UNTIL_KEY, snapshot.market_halted_until).ignore();
+        }
+        if !snapshot.order_ids.is_empty() {
+            pipe.sadd(ORDER_IDS_KEY, &snapshot.order_ids).ignore();
+        }
+        pipe.set(RESTORED_KEY, snapshot.timestamp).ignore();
+
+        // Written from scratch, whatever this storage wrote before is gone
+        {
+            let mut store = self.store.lock().expect("Order book store lock poisoned");
+            *store = OrderBookStore::new();
+            for order_book in &snapshot.order_books {
+                // An empty book has no orders to add, it is still a book
+                pipe.sadd(ORDER_BOOKS_KEY, &order_book.stock_symbol).ignore();
+                store.save(&mut pipe, order_book);
+            }
+        }
+
+        let _: () = pipe.query_async(&mut conn).await?;
+
+        Ok(())
+    }
+}
+
+impl Storage for RedisStorage {
+    async fn prices(&self) -> StorageResult<HashMap<String, Price>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.hgetall(STOCK_PRICES_KEY).await?)
+    }
+
+    async fn price(&self, stock_symbol: &str) -> StorageResult<Option<Price>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.hget(STOCK_PRICES_KEY, stock_symbol).await?)
+    }
+
+    async fn set_price(&self, stock_symbol: &str, price: Price) -> StorageResult<()> {
+        let mut conn = self.conn.clone();
+        let _: () = conn.hset(STOCK_PRICES_KEY, stock_symbol, price).await?;
+        Ok(())
+    }
+
+    async fn sectors(&self) -> StorageResult<HashMap<String, String>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.hgetall(STOCK_SECTOR_KEY).await?)
+    }
+
+    // The lot size and minimum quantity default to 1 when missing
+    async fn reference_data(&self) -> StorageResult<HashMap<String, ReferenceData>> {
+        let mut conn = self.conn.clone();
+        let tick_sizes: HashMap<String, Price> = conn.hgetall(TICK_SIZE_KEY).await?;
+        let lot_sizes: HashMap<String, u32> = conn.hgetall(LOT_SIZE_KEY).await?;
+        let min_quantities: HashMap<String, u32> = conn.hgetall(MIN_QUANTITY_KEY).await?;
+        let price_bands: HashMap<String, f64> = conn.hgetall(PRICE_BAND_KEY).await?;
+
+        let reference_data = tick_sizes
+            .into_iter()
+            .map(|(stock_symbol, tick_size)| {
+                let reference_data = ReferenceData {
+                    tick_size,
+                    lot_size: lot_sizes.get(&stock_symbol).copied().unwrap_or(1),
+                    min_quantity: min_quantities.get(&stock_symbol).copied().unwrap_or(1),
+                    price_band: price_bands.get(&stock_symbol).copied(),
+                };
+                (stock_symbol, reference_data)
+            })
+            .collect();
+
+        Ok(reference_data)
+    }
+
+    // Never touches what the order book manager last saved, the market data generator reads through a clone of the same storage
+    async fn order_books(&self) -> StorageResult<Vec<SavedOrderBook>> {
+        let mut conn = self.conn.clone();
+
+        let mut order_books = Vec::new();
+        for stock_symbol in read_stock_symbols(&mut conn).await? {
+            let side_orders = read_prioritized_orders(&mut conn, &stock_symbol, &BOOK_SIDES, None).await?;
+            let halted_until: Option<u64> = conn.get(halted_until_key(&stock_symbol)).await?;
+            order_books.push(saved_order_book(&stock_symbol, halted_until, BOOK_SIDES.into_iter().zip(side_orders)));
+        }
+
+        Ok(order_books)
+    }
+
+    // Both sides of a book are read by one script, so they are from the same save
+    async fn resting_orders(&self) -> StorageResult<RestingOrders> {
+        let mut conn = self.conn.clone();
+
+        let mut resting_orders = HashMap::new();
+        for stock_symbol in read_stock_symbols(&mut conn).await? {
+            let mut sides = read_orders(&mut conn, &stock_symbol, &[BookSide::Bids, BookSide::Asks], None).await?.into_iter();
+            let (buy_orders, sell_orders) = (sides.next().unwrap_or_default(), sides.next().unwrap_or_default());
+            resting_orders.insert(stock_symbol, (buy_orders, sell_orders));
+        }
+
+        Ok(resting_orders)
+    }
+
+    // Only the scores and shown quantities of the top levels, each side on its own so they can be one save apart
+    async fn depth_levels(&self, stock_symbol: &str, levels: usize) -> StorageResult<(Vec<DepthLevel>, Vec<DepthLevel>)> {
+        let mut conn = self.conn.clone();
+        let bids = read_levels(&mut conn, stock_symbol, BookSide::Bids, levels).await?;
+        let asks = read_levels(&mut conn, stock_symbol, BookSide::Asks, levels).await?;
+        Ok((bids, asks))
+    }
+
+    // Books still in the layout from before the sorted sets are moved over first
+    // What is read becomes what was last written, so the first save of a book doesn't rewrite what didn't change
+    async fn load_order_books(&self) -> StorageResult<Vec<SavedOrderBook>> {
+        let mut conn = self.conn.clone();
+        self.migrate_legacy_order_books(&mut conn).await?;
+
+        let mut order_books = Vec::new();
+        for stock_symbol in read_stock_symbols(&mut conn).await? {
+            let side_orders = read_prioritized_orders(&mut conn, &stock_symbol, &BOOK_SIDES, None).await?;
+            let sides: Vec<(BookSide, Vec<(u64, Order)>)> = BOOK_SIDES.into_iter().zip(side_orders).collect();
+            let halted_until: Option<u64> = conn.get(halted_until_key(&stock_symbol)).await?;
+
+            self.store.lock().expect("Order book store lock poisoned").remember(&stock_symbol, halted_until, &sides);
+            order_books.push(saved_order_book(&stock_symbol, halted_until, sides));
+        }
+
+        Ok(order_books)
+    }
+
+    // Everything goes in one MULTI/EXEC, so a reader never sees a book with only one side of a fill applied,
+    // or only some of the books a request changed (a market-wide halt changes all of them)
+    // Only the changes since the last save are sent, see OrderBookStore
+    async fn save_order_books(&self, order_books: Vec<SavedOrderBook>, sequence: u64, market_halted_until: u64) -> StorageResult<()> {
+        let mut conn = self.conn.clone();
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        let before = {
+            let mut store = self.store.lock().expect("Order book store lock poisoned");
+            let before = store.stored_books(order_books.iter().map(|order_book| order_book.stock_symbol.as_str()));
+            for order_book in &order_books {
+                store.save(&mut pipe, order_book);
+            }
+            before
+        };
+
+        pipe.set(SEQUENCE_KEY, sequence).ignore().set(MARKET_HALTED_UNTIL_KEY, market_halted_until).ignore();
+        let saved: StorageResult<()> = pipe.query_async(&mut conn).await.map_err(Into::into);
+        // Nothing was written, the order book manager saves these books again and they are compared against what is really in Redis
+        if saved.is_err() {
+            self.store.lock().expect("Order book store lock poisoned").restore_books(before);
+        }
+        saved
+    }
+
+    async fn sequence(&self) -> StorageResult<Option<u64>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.get(SEQUENCE_KEY).await?)
+    }
+
+    async fn market_halted_until(&self) -> StorageResult<u64> {
+        let mut conn = self.conn.clone();
+        let market_halted_until: Option<u64> = conn.get(MARKET_HALTED_UNTIL_KEY).await?;
+        Ok(market_halted_until.unwrap_or(0))
+    }
+
+    async fn take_restored(&self) -> StorageResult<bool> {
+        let mut conn = self.conn.clone();
+        let restored: Option<u64> = redis::cmd("GETDEL").arg(RESTORED_KEY).query_async(&mut conn).await?;
+        Ok(restored.is_some())
+    }
+
+    async fn order_ids(&self) -> StorageResult<HashSet<String>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.smembers(ORDER_IDS_KEY).await?)
+    }
+
+    async fn add_order_id(&self, order_id: &str) -> StorageResult<bool> {
+        let mut conn = self.conn.clone();
+        Ok(conn.sadd(ORDER_IDS_KEY, order_id).await?)
+    }
+}
+
+impl RedisStorage {
+    // Before the sorted sets every book was one hash order_book:{symbol} with each side as a JSON array of its orders in priority order
+    // Each one is written in the new layout and deleted in the same MULTI/EXEC, so a crash halfway leaves every book in one of the two
+    async fn migrate_legacy_order_books(&self, conn: &mut aio::MultiplexedConnection) -> StorageResult<()> {
+        // Keys of the new layout have more parts after the stock symbol
+        let keys: Vec<String> = scan_keys(conn, "order_book:*")
+            .await?
+            .into_iter()
+            .filter(|key| !key.trim_start_matches("order_book:").contains(':'))
+            .collect();
+
+        for key in keys {
+            let (buy_orders_string, sell_orders_string, stop_orders_string): (Option<String>, Option<String>, Option<String>) = conn
+                .hget(&key, &["buy_orders", "sell_orders", "stop_orders"])
+                .await?;
+            // A book that can't be read is left where it is, the order book manager doesn't start on half of the market
+            let parse = |orders_string: Option<String>| -> StorageResult<Vec<Order>> {
+                let Some(orders) = orders_string else {
+                    return Ok(Vec::new());
+                };
+                serde_json::from_str(&orders).map_err(|e| StorageError(format!("Failed to deserialize legacy order book {}: {}", key, e)))
+            };
+
+            let order_book = SavedOrderBook {
+                stock_symbol: key.trim_start_matches("order_book:").to_string(),
+                buy_orders: parse(buy_orders_string)?,
+                sell_orders: parse(sell_orders_string)?,
+                stop_orders: parse(stop_orders_string)?,
+                halted_until: None,
+            };
+
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            self.store.lock().expect("Order book store lock poisoned").save(&mut pipe, &order_book);
+            let _: () = pipe
+                .sadd(ORDER_BOOKS_KEY, &order_book.stock_symbol)
+                .ignore()
+                .del(&key)
+                .ignore()
+                .query_async(conn)
+                .await?;
+
+            println!("RedisStorage: Migrated {} to the sorted-set layout", key);
+        }
+
+        Ok(())
+    }
+}
+
+// Book as read from Redis, every side in priority order
+fn saved_order_book(stock_symbol: &str, halted_until: Option<u64>, sides: impl IntoIterator<Item = (BookSide, Vec<(u64, Order)>)>) -> SavedOrderBook {
+    let mut order_book = SavedOrderBook { stock_symbol: stock_symbol.to_string(), halted_until, ..Default::default() };
+    for (side, prioritized) in sides {
+        let orders = match side {
+            BookSide::Bids => &mut order_book.buy_orders,
+            BookSide::Asks => &mut order_book.sell_orders,
+            BookSide::Stops => &mut order_book.stop_orders,
+        };
+        orders.extend(prioritized.into_iter().map(|(_, order)| order));
+    }
+
+    order_book
+}
+
+async fn scan_keys(conn: &mut aio::MultiplexedConnection, pattern: &str) -> StorageResult<Vec<String>> {
+    let mut iter: redis::AsyncIter<String> = conn.scan_match(pattern).await?;
+
+    let mut keys = Vec::new();
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+
+    Ok(keys)
+}
+
+// Where an order is in Redis, to only write the orders that changed since the last save
+#[derive(Debug, Clone, PartialEq)]
+struct StoredOrder {
+    side: BookSide,
+    priority: u64,
+    score: f64,
+    order_string: String,
+}
+
+// What was last written of one book
+#[derive(Debug, Clone, Default)]
+struct StoredBook {
+    orders: HashMap<String, StoredOrder>,
+    halted_until: Option<u64>,
+}
+
+// Keeps what was last written for every book, a save only sends the orders that were added, changed or removed
+pub struct OrderBookStore {
+    stored: HashMap<String, StoredBook>,
+    // Priority given to the next order that goes to the back of its price level, higher than any priority in Redis
+    next_priority: u64,
+}
+
+impl Default for OrderBookStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderBookStore {
+    // Nothing written yet, the first save of every book writes all of its orders
+    pub fn new() -> Self {
+        Self { stored: HashMap::new(), next_priority: 1 }
+    }
+
+    // Add the commands that bring the book in Redis up to date to the pipe
+    // Orders keep their priority while nothing moves them behind another order of their level, otherwise they get a new one at the back
+    pub fn save(&mut self, pipe: &mut Pipeline, order_book: &SavedOrderBook) {
+        let stock_symbol = order_book.stock_symbol.as_str();
+        let StoredBook { orders: mut stored, halted_until } = self.stored.remove(stock_symbol).unwrap_or_default();
+        let mut current: HashMap<String, StoredOrder> = HashMap::new();
+
+        let sides = [
+            (BookSide::Bids, &order_book.buy_orders),
+            (BookSide::Asks, &order_book.sell_orders),
+            (BookSide::Stops, &order_book.stop_orders),
+        ];
+        for (side, orders) in sides {
+            let mut level_score: Option<f64> = None;
+            let mut last_priority: u64 = 0;
+
+            for order in orders {
+                let score = score(side, order.price);
+                if level_score != Some(score) {
+                    level_score = Some(score);
+                    last_priority = 0;
+                }
+
+                let before = stored.remove(&order.id);
+                let priority = match &before {
+                    Some(before) if before.side == side && before.score == score && before.priority > last_priority => before.priority,
+                    _ => {
+                        self.next_priority += 1;
+                        self.next_priority - 1
+                    }
+                };
+                last_priority = priority;
+
+                let order_string = serde_json::to_string(order).expect("Failed to serialize order");
+                let after = StoredOrder { side, priority, score, order_string };
+                if before.as_ref() != Some(&after) {
+                    if let Some(before) = before.filter(|before| (before.side, before.priority) != (side, priority)) {
+                        remove_member(pipe, stock_symbol, before.side, &member(before.priority, &order.id));
+                    }
+                    add_order(pipe, side, priority, order);
+                }
+                current.insert(order.id.clone(), after);
+            }
+        }
+
+        // Whatever is left was filled, cancelled or expired
+        for (order_id, before) in stored {
+            remove_member(pipe, stock_symbol, before.side, &member(before.priority, &order_id));
+            remove_order(pipe, stock_symbol, &order_id);
+        }
+
+        if order_book.halted_until != halted_until {
+            match order_book.halted_until {
+                Some(halted_until) => pipe.set(halted_until_key(stock_symbol), halted_until).ignore(),
+                None => pipe.del(halted_until_key(stock_symbol)).ignore(),
+            };
+        }
+
+        self.stored.insert(stock_symbol.to_string(), StoredBook { orders: current, halted_until: order_book.halted_until });
+    }
+
+    // Take a book as read from Redis as what was last written, with the priorities it has there
+    fn remember(&mut self, stock_symbol: &str, halted_until: Option<u64>, sides: &[(BookSide, Vec<(u64, Order)>)]) {
+        let mut stored: HashMap<String, StoredOrder> = HashMap::new();
+        for (side, prioritized) in sides {
+            for (priority, order) in prioritized {
+                self.next_priority = self.next_priority.max(priority + 1);
+                let order_string = serde_json::to_string(order).expect("Failed to serialize order");
+                stored.insert(order.id.clone(), StoredOrder { side: *side, priority: *priority, score: score(*side, order.price), order_string });
+            }
+        }
+
+        self.stored.insert(stock_symbol.to_string(), StoredBook { orders: stored, halted_until });
+    }
+
+    fn stored_books<'a>(&self, stock_symbols: impl Iterator<Item = &'a str>) -> Vec<(String, Option<StoredBook>)> {
+        stock_symbols
+            .map(|stock_symbol| (stock_symbol.to_string(), self.stored.get(stock_symbol).cloned()))
+            .collect()
+    }
+
+    fn restore_books(&mut self, books: Vec<(String, Option<StoredBook>)>) {
+        for (stock_symbol, stored) in books {
+            match stored {
+                Some(stored) => self.stored.insert(stock_symbol, stored),
+                None => self.stored.remove(&stock_symbol),
+            };
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader, BufWriter};
+
+use crate::models::{DepthLevel, Order, ReferenceData};
+use crate::price::Price;
+
+// Goes up every time the snapshot format changes, a snapshot of another version is refused instead of half restored
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// Error of a storage backend, with the message of the backend's own error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageError(pub String);
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Storage error: {}", self.0)
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<redis::RedisError> for StorageError {
+    fn from(error: redis::RedisError) -> Self {
+        StorageError(error.to_string())
+    }
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+// Resting buy and sell orders of every stock symbol with an order book
+pub type RestingOrders = HashMap<String, (Vec<Order>, Vec<Order>)>;
+
+// Orders of one order book as they are saved, every side in priority order
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SavedOrderBook {
+    pub stock_symbol: String,
+    pub buy_orders: Vec<Order>,
+    pub sell_orders: Vec<Order>,
+    pub stop_orders: Vec<Order>,
+    // When the halt of the book ends, kept after that until the book is reopened with an auction, None if it isn't halted
+    #[serde(default)]
+    pub halted_until: Option<u64>,
+}
+
+// Everything the stock side keeps about the market, written to a file by setup snapshot and read back by setup restore
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MarketSnapshot {
+    pub version: u32,
+    // When it was taken, seconds since the epoch
+    pub timestamp: u64,
+    pub prices: BTreeMap<String, Price>,
+    pub sectors: BTreeMap<String, String>,
+    pub reference_data: BTreeMap<String, ReferenceData>,
+    // Last sequence number the order book manager assigned, so new orders keep coming after the restored ones
+    pub sequence: Option<u64>,
+    // End of the last market-wide halt, 0 if the market was never halted
+    #[serde(default)]
+    pub market_halted_until: u64,
+    pub order_books: Vec<SavedOrderBook>,
+    // Id of every order ever accepted, so a restored market still rejects them as duplicates
+    #[serde(default)]
+    pub order_ids: BTreeSet<String>,
+}
+
+// Where the stock side keeps the prices, sectors, reference data and order books between restarts
+// The order book manager and the market data generator only go through it, so they run the same on Redis or in memory
+// Only the order book manager saves order books, and never two saves at once
+pub trait Storage: Send + Sync {
+    // Current price of every stock
+    fn prices(&self) -> impl Future<Output = StorageResult<HashMap<String, Price>>> + Send;
+
+    fn price(&self, stock_symbol: &str) -> impl Future<Output = StorageResult<Option<Price>>> + Send;
+
+    fn set_price(&self, stock_symbol: &str, price: Price) -> impl Future<Output = StorageResult<()>> + Send;
+
+    // Sector of every stock
+    fn sectors(&self) -> impl Future<Output = StorageResult<HashMap<String, String>>> + Send;
+
+    // Reference data of every stock that has a tick size
+    fn reference_data(&self) -> impl Future<Output = StorageResult<HashMap<String, ReferenceData>>> + Send;
+
+    // Every order book, sorted by stock symbol, only for reading them
+    fn order_books(&self) -> impl Future<Output = StorageResult<Vec<SavedOrderBook>>> + Send;
+
+    // Resting buy and sell orders of every order book in priority order, without the stop orders
+    fn resting_orders(&self) -> impl Future<Output = StorageResult<RestingOrders>> + Send;
+
+    // Top levels of the bids and asks of a stock like in a depth snapshot, read without the orders themselves
+    // Empty if the stock has no order book
+    fn depth_levels(&self, stock_symbol: &str, levels: usize) -> impl Future<Output = StorageResult<(Vec<DepthLevel>, Vec<DepthLevel>)>> + Send;
+
+    // Every order book to keep saving them, called once by the order book manager when it starts
+    // What is loaded is what the following saves of the same storage start from
+    fn load_order_books(&self) -> impl Future<Output = StorageResult<Vec<SavedOrderBook>>> + Send;
+
+    // Replace the saved order books with these and save the last assigned sequence number and the end of the market-wide halt,
+    // all of it or nothing
+    fn save_order_books(&self, order_books: Vec<SavedOrderBook>, sequence: u64, market_halted_until: u64) -> impl Future<Output = StorageResult<()>> + Send;
+
+    // Last sequence number saved with the order books
+    fn sequence(&self) -> impl Future<Output = StorageResult<Option<u64>>> + Send;
+
+    // End of the market-wide halt saved with the order books, 0 if the market was never halted
+    fn market_halted_until(&self) -> impl Future<Output = StorageResult<u64>> + Send;
+
+    // Whether the market was replaced from outside the order book manager (setup init or restore) since it last started
+    // Cleared by reading it, the journal starts over from the order books loaded after it instead of following on from its own
+    fn take_restored(&self) -> impl Future<Output = StorageResult<bool>> + Send;
+
+    // Id of every accepted order
+    fn order_ids(&self) -> impl Future<Output = StorageResult<HashSet<String>>> + Send;
+
+    // Remember the id of an accepted order, false if it was already known
+    fn add_order_id(&self, order_id: &str) -> impl Future<Output = StorageResult<bool>> + Send;
+}
+
+// Take a snapshot of everything in the storage
+pub async fn read_snapshot<S: Storage>(storage: &S, timestamp: u64) -> StorageResult<MarketSnapshot> {
+    Ok(MarketSnapshot {
+        version: SNAPSHOT_VERSION,
+        timestamp,
+        prices: storage.prices().await?.into_iter().collect(),
+        sectors: storage.sectors().await?.into_iter().collect(),
+        reference_data: storage.reference_data().await?.into_iter().collect(),
+        sequence: storage.sequence().await?,
+        market_halted_until: storage.market_halted_until().await?,
+        order_books: storage.order_books().await?,
+        order_ids: storage.order_ids().await?.into_iter().collect(),
+    })
+}
+
+// Write the snapshot to a JSON file
+pub fn write_snapshot_file(path: &str, snapshot: &MarketSnapshot) -> io::Result<()> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(BufWriter::new(file), snapshot).map_err(io::Error::from)
+}
+
+// Read a snapshot written by write_snapshot_file, a file of another snapshot version is an error
+pub fn read_snapshot_file(path: &str) -> io::Result<MarketSnapshot> {
+    let file = File::open(path)?;
+    let snapshot: MarketSnapshot = serde_json::from_reader(BufReader::new(file)).map_err(io::Error::from)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        let message = format!("Snapshot {} is version {}, only version {} can be read", path, snapshot.version, SNAPSHOT_VERSION);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+
+    Ok(snapshot)
+}
\ No newline at end of file
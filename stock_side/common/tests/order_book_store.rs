@@ -0,0 +1,97 @@
+use common::models::{Order, OrderKind, OrderType, TimeInForce};
+use common::price::Price;
+use common::redis_storage::OrderBookStore;
+use common::storage::SavedOrderBook;
+use redis::Arg;
+
+fn order(sequence: u64, order_type: OrderType, quantity: u32, price: f64) -> Order {
+    Order {
+        id: format!("order-{}", sequence),
+        stock_symbol: "AAPL".to_string(),
+        order_type,
+        account_id: None,
+        kind: OrderKind::Limit,
+        quantity,
+        price: Price::from_f64(price),
+        stop_price: None,
+        trailing_offset: None,
+        post_only: None,
+        peg: None,
+        display_quantity: None,
+        timestamp: 0,
+        partial_fill: true,
+        time_in_force: TimeInForce::GoodTillCancel,
+        expire_time: None,
+        sequence,
+        filled_quantity: 0,
+        average_price: Price::ZERO,
+        visible_quantity: 0,
+    }
+}
+
+// Every command of the pipe as its name and first key
+fn commands(pipe: &redis::Pipeline) -> Vec<(String, String)> {
+    pipe.cmd_iter()
+        .map(|cmd| {
+            let args: Vec<String> = cmd
+                .args_iter()
+                .map(|arg| match arg {
+                    Arg::Simple(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+                    Arg::Cursor => String::new(),
+                })
+                .collect();
+            (args[0].clone(), args[1].clone())
+        })
+        .collect()
+}
+
+fn order_book(buy_orders: Vec<Order>) -> SavedOrderBook {
+    SavedOrderBook { stock_symbol: "AAPL".to_string(), buy_orders, ..Default::default() }
+}
+
+#[test]
+fn only_changed_orders_are_written() {
+    let order_1 = order(1, OrderType::Buy, 100, 100.0);
+    let order_2 = order(2, OrderType::Buy, 100, 99.0);
+
+    let mut store = OrderBookStore::new();
+    let mut pipe = redis::pipe();
+    store.save(&mut pipe, &order_book(vec![order_1.clone(), order_2.clone()]));
+    let zadds = commands(&pipe).into_iter().filter(|(name, _)| name == "ZADD").count();
+    assert_eq!(zadds, 2);
+
+    // Nothing changed since the last save
+    let mut pipe = redis::pipe();
+    store.save(&mut pipe, &order_book(vec![order_1, order_2.clone()]));
+    assert!(commands(&pipe).is_empty());
+
+    // Order 1 is filled, only it is removed
+    let mut pipe = redis::pipe();
+    store.save(&mut pipe, &order_book(vec![order_2]));
+    assert_eq!(
+        commands(&pipe),
+        vec![
+            ("ZREM".to_string(), "order_book:AAPL:bids".to_string()),
+            ("DEL".to_string(), "order_book:AAPL:order:order-1".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn halt_deadline_is_written_when_it_changes() {
+    let mut store = OrderBookStore::new();
+    let halted = SavedOrderBook { halted_until: Some(1_300), ..order_book(Vec::new()) };
+
+    let mut pipe = redis::pipe();
+    store.save(&mut pipe, &halted);
+    assert_eq!(commands(&pipe), vec![("SET".to_string(), "order_book:AAPL:halted_until".to_string())]);
+
+    let mut pipe = redis::pipe();
+    store.save(&mut pipe, &halted);
+    assert!(commands(&pipe).is_empty());
+
+    // Reopened
+    let mut pipe = redis::pipe();
+    store.save(&mut pipe, &order_book(Vec::new()));
+    assert_eq!(commands(&pipe), vec![("DEL".to_string(), "order_book:AAPL:halted_until".to_string())]);
+}
@@ -0,0 +1,14 @@
+use common::models::{Order, OrderKind, OrderType, TimeInForce};
+use common::price::Price;
+
+#[test]
+fn market_order_can_be_sent_without_a_price() {
+    let json = r#"{"id":"order-1","stock_symbol":"AAPL","order_type":"Buy","kind":"Market","quantity":10,"timestamp":0,"partial_fill":true}"#;
+
+    let order: Order = serde_json::from_str(json).unwrap();
+
+    assert_eq!(order.kind, OrderKind::Market);
+    assert_eq!(order.order_type, OrderType::Buy);
+    assert_eq!(order.price, Price::ZERO);
+    assert_eq!(order.time_in_force, TimeInForce::GoodTillCancel);
+}
@@ -1,4 +1,4 @@
-use common::models::Order;
+use common::models::{Order, OrderRequest};
 use rdkafka::config::ClientConfig;
 use rdkafka::consumer::{Consumer, StreamConsumer};
 use rdkafka::message::{BorrowedMessage, Message};
@@ -38,14 +38,14 @@ impl OrderConsumer {
         }
     }
 
-    pub async fn consume_messages(&self, order_sender: Sender<Order>) {
+    pub async fn consume_messages(&self, order_sender: Sender<OrderRequest>) {
         let mut stream = self.consumer.stream();
 
         while let Some(result) = stream.next().await {
             match result {
                 Ok(message) => {
-                    if let Some(order) = parse_order(&message) {
-                        if let Err(e) = order_sender.send(order).await {
+                    if let Some(order_request) = parse_order_request(&message) {
+                        if let Err(e) = order_sender.send(order_request).await {
                             eprintln!("Failed to send order: {}", e);
                         }
                     }
@@ -58,12 +58,17 @@ impl OrderConsumer {
     }
 }
 
-fn parse_order(message: &BorrowedMessage) -> Option<Order> {
+// The payload is either a tagged OrderRequest ({"Cancel": {...}}) or a bare Order, which is treated as a new order
+fn parse_order_request(message: &BorrowedMessage) -> Option<OrderRequest> {
     if let Some(payload) = message.payload() {
+        if let Ok(order_request) = from_slice::<OrderRequest>(payload) {
+            return Some(order_request);
+        }
+
         match from_slice::<Order>(payload) {
-            Ok(order) => Some(order),
+            Ok(order) => Some(OrderRequest::New(order)),
             Err(e) => {
-                eprintln!("Failed to deserialize order: {}", e);
+                eprintln!("Failed to deserialize order request: {}", e);
                 None
             }
         }
@@ -1,6 +1,7 @@
 use rdkafka::config::ClientConfig;
 use rdkafka::producer::{FutureProducer, FutureRecord};
 use rdkafka::util::Timeout;
+use serde::Serialize;
 
 use common::models::Stock;
 
@@ -33,7 +34,7 @@ impl StockProducer {
             .payload(&stock_json);
 
         match self.producer.send(record, Timeout::Never).await {
-            Ok(_) => {()}
+            Ok(_) => {}
             Err((e, _)) => {
                 eprintln!("Failed to produce stock: {}", e);
             }
@@ -45,4 +46,44 @@ impl StockProducer {
             self.produce_stock(stock, topic).await;
         }
     }
+}
+
+// Publishes every message of one type as JSON to one topic
+// key gives the Kafka key of a message, messages with the same key land in the same partition and stay in the order they were produced
+pub struct JsonProducer<T: Serialize> {
+    producer: FutureProducer,
+    topic: String,
+    key: fn(&T) -> &str,
+}
+
+impl<T: Serialize> JsonProducer<T> {
+    pub fn new(brokers: &str, topic: &str, key: fn(&T) -> &str) -> Self {
+        println!("JsonProducer: Connecting to Kafka for {}: {}", topic, brokers);
+
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", "5000")  // 5 seconds
+            .create()
+            .expect("JsonProducer: Producer creation failed");
+
+        println!("JsonProducer: Connected to Kafka for {}", topic);
+
+        Self {
+            producer,
+            topic: topic.to_string(),
+            key,
+        }
+    }
+
+    pub async fn produce(&self, message: &T) {
+        let message_json = serde_json::to_string(message).expect("Failed to serialize message");
+
+        let record = FutureRecord::to(&self.topic)
+            .key((self.key)(message))
+            .payload(&message_json);
+
+        if let Err((e, _)) = self.producer.send(record, Timeout::Never).await {
+            eprintln!("Failed to produce to {}: {}", self.topic, e);
+        }
+    }
 }
\ No newline at end of file
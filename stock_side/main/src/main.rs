@@ -1,15 +1,53 @@
-use common::models::{Order, Stock, Trade};
+use common::initial_market::{reference_data, sector, AVAILABLE_STOCKS, INITIAL_PRICE};
+use common::memory_storage::MemoryStorage;
+use common::models::{
+    AuctionInfo, DepthMessage, ExecutionReport, OrderExpired, OrderRequest, OrderRequestAck, RejectReason, SessionPhase, Stock, Trade,
+    TradingStatus,
+};
+use common::redis_storage::RedisStorage;
+use common::price::Price;
+use common::storage::{read_snapshot_file, Storage};
 use communication_layer::consumer::OrderConsumer;
-use communication_layer::producer::StockProducer;
+use communication_layer::producer::{JsonProducer, StockProducer};
 use market_data_generator::price_updater::MarketDataGenrator;
+use order_management_system::execution_report::{new_ack, new_rejected_execution_report};
+use order_management_system::order_book::SelfTradePrevention;
+use order_management_system::matching_engine::{CallAuction, CircuitBreakers, EngineSettings, OrderEvents, TradingHours};
 use order_management_system::order_book_manager::OrderBookManager;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
+const REDIS_URL: &str = "redis://localhost:6379";
+
+// main                          keep the market in Redis
+// main --in-memory [snapshot]   keep the market in memory, nothing is saved, starting from a file written by setup snapshot
+//                               or from the prices, sectors and reference data of setup init with no order books
 #[tokio::main(flavor = "multi_thread", worker_threads = 8)]
 async fn main() {
-    let (oms_sender, mut oms_receiver): (Sender<Order>, Receiver<Order>) = channel(100);
+    let args: Vec<String> = std::env::args().collect();
+    match args.iter().skip(1).map(String::as_str).collect::<Vec<&str>>()[..] {
+        [] => run(RedisStorage::connect(REDIS_URL).await.expect("Failed to connect to Redis")).await,
+        ["--in-memory"] => {
+            println!("Running in memory from the initial market, {} stocks and no order books", AVAILABLE_STOCKS.len());
+            run(initial_market().await).await
+        }
+        ["--in-memory", path] => {
+            let snapshot = read_snapshot_file(path).expect("Failed to read snapshot file");
+            println!("Running in memory from {}, {} stocks and {} order books", path, snapshot.prices.len(), snapshot.order_books.len());
+            run(MemoryStorage::from_snapshot(snapshot)).await
+        }
+        _ => {
+            eprintln!("Usage: main [--in-memory [snapshot file]]");
+            std::process::exit(2);
+        }
+    }
+}
+
+// The order book manager and the market data generator share the storage, clones of it are the same backend
+async fn run<S: Storage + Clone + 'static>(storage: S) {
+    let (oms_sender, mut oms_receiver): (Sender<OrderRequest>, Receiver<OrderRequest>) = channel(100);
     let (mdg_sender, mdg_receiver): (Sender<Trade>, Receiver<Trade>) = channel(100);
     let (stock_sender, mut stock_receiver): (Sender<Stock>, Receiver<Stock>) = channel(100);
 
@@ -18,7 +56,27 @@ async fn main() {
     const TO_CONSUME_TOPIC: &str = "broker-orders";
     const GROUP_ID: &str = "oms_consumer_group";
     const TO_PRODUCE_TOPIC: &str = "stock-prices";
-    const REDIS_URL: &str = "redis://localhost:6379";
+    // Every order, cancel and trade of the order book manager, replay it with the replay_journal binary of the order management system
+    const JOURNAL_PATH: &str = "oms-journal.jsonl";
+    const ORDER_EXPIRED_TOPIC: &str = "order-expired";
+    const ORDER_REQUEST_ACK_TOPIC: &str = "order-request-acks";
+    const EXECUTION_REPORT_TOPIC: &str = "execution-reports";
+    const TRADE_TOPIC: &str = "trades";
+    const DEPTH_TOPIC: &str = "order-book-depth";
+    const AUCTION_INFO_TOPIC: &str = "auction-info";
+    const TRADING_STATUS_TOPIC: &str = "trading-status";
+    const SESSION_OPEN_UTC: u64 = 13 * 60 * 60; // 13:00 UTC, 9am New York
+    const SESSION_CLOSE_UTC: u64 = 21 * 60 * 60; // 21:00 UTC, 4pm New York
+    // Closed from the session close until the opening call
+    const TRADING_HOURS: TradingHours = TradingHours { open: SESSION_OPEN_UTC, close: SESSION_CLOSE_UTC };
+    const SELF_TRADE_PREVENTION: SelfTradePrevention = SelfTradePrevention::CancelNewest;
+    // Opening call 13:00-13:30 UTC (9:00-9:30am New York), closing call in the last 10 minutes before the session close
+    const CALL_AUCTIONS: [CallAuction; 2] = [
+        CallAuction { phase: SessionPhase::PreOpen, start: SESSION_OPEN_UTC, end: SESSION_OPEN_UTC + 30 * 60 },
+        CallAuction { phase: SessionPhase::PreClose, start: SESSION_CLOSE_UTC - 10 * 60, end: SESSION_CLOSE_UTC },
+    ];
+    // A stock that moves 10% halts on its own, the whole market halts when stocks moved 7% on average, both reopen 5 minutes later
+    const CIRCUIT_BREAKERS: CircuitBreakers = CircuitBreakers { price_band: 0.1, market_wide_move: 0.07, cool_down: 5 * 60 };
 
     let consumer = OrderConsumer::new(BROKERS, TO_CONSUME_TOPIC, GROUP_ID);
     
@@ -27,61 +85,93 @@ async fn main() {
         panic!("Consumer stopped");
     });
 
-    // This need ARC, because it will be shared between the 2 thread (process_order and add_to_orderbook)
-        // No need Mutex, because they use different function, do different thing
-    let order_book_manager = OrderBookManager::new(REDIS_URL).await;
-    let order_book_manager_clone = Arc::new(order_book_manager);
+    // This need ARC, because it will be shared between the 3 thread (process_order, expire_orders and the stock prices)
+        // No need Mutex here, the order books inside the manager are already behind one
+    let settings = EngineSettings {
+        self_trade_prevention: SELF_TRADE_PREVENTION,
+        reference_data: Default::default(),
+        call_auctions: CALL_AUCTIONS.to_vec(),
+        trading_hours: Some(TRADING_HOURS),
+        circuit_breakers: CIRCUIT_BREAKERS,
+    };
+    let order_book_manager = Arc::new(OrderBookManager::new(storage.clone(), JOURNAL_PATH, SESSION_CLOSE_UTC, settings).await);
+
+    // Shared by the 3 thread as well, everything the order book manager produces goes out through it
+    let order_event_publisher = Arc::new(OrderEventPublisher {
+        mdg_sender,
+        // Keyed by stock symbol so the trades of one stock stay in the order they happened
+        trade_producer: JsonProducer::new(BROKERS, TRADE_TOPIC, |trade: &Trade| &trade.stock_symbol),
+        // Keyed by order id so every event of the same order stays in order
+        execution_report_producer: JsonProducer::new(BROKERS, EXECUTION_REPORT_TOPIC, |execution_report: &ExecutionReport| &execution_report.order_id),
+        order_request_ack_producer: JsonProducer::new(BROKERS, ORDER_REQUEST_ACK_TOPIC, |ack: &OrderRequestAck| &ack.order_id),
+        order_expired_producer: JsonProducer::new(BROKERS, ORDER_EXPIRED_TOPIC, |order_expired: &OrderExpired| &order_expired.order_id),
+        // Snapshots and updates of a stock share the stock symbol key, so a snapshot stays in order with the updates around it
+        depth_producer: JsonProducer::new(BROKERS, DEPTH_TOPIC, |depth: &DepthMessage| match depth {
+            DepthMessage::Snapshot(snapshot) => &snapshot.stock_symbol,
+            DepthMessage::Update(update) => &update.stock_symbol,
+        }),
+        auction_info_producer: JsonProducer::new(BROKERS, AUCTION_INFO_TOPIC, |auction_info: &AuctionInfo| &auction_info.stock_symbol),
+        trading_status_producer: JsonProducer::new(BROKERS, TRADING_STATUS_TOPIC, |trading_status: &TradingStatus| &trading_status.stock_symbol),
+    });
 
-    // every 500ms, check the order book, to see any trades can be made
-    let order_book_manager_process_order_handle = tokio::spawn({
-        let order_book_manager = order_book_manager_clone.clone();
+    // Everytime receive an order, match it against the order book straight away
+    // Cancel and replace requests are answered with an ack or reject on the ack topic, depth snapshot requests on the depth topic
+    // A new order or replace that fails on the storage is rejected as well, so every request gets an answer
+    let order_book_manager_handle = tokio::spawn({
+        let order_book_manager = order_book_manager.clone();
+        let order_event_publisher = order_event_publisher.clone();
         async move {
-            loop {
-                // Process the order
-                match order_book_manager.process_order().await {
-                    Ok(Some(trade)) => {
-                        // Send the trade to the trading side
-                        println!("Trade: {:?}", trade);
-                        if let Err(e) = mdg_sender.send(trade).await {
-                            eprintln!("Failed to send trade via mdg_sender: {}", e);
+            while let Some(order_request) = oms_receiver.recv().await {
+                let events = match order_request {
+                    // New orders and replaces read the stock price first, they fail before they reach the order book
+                    OrderRequest::New(order) => match order_book_manager.process_order(order.clone()).await {
+                        Ok(events) => events,
+                        Err(e) => {
+                            eprintln!("Failed to process order {}: {}", order.id, e);
+                            let mut events = OrderEvents::default();
+                            events.execution_reports.push(new_rejected_execution_report(&order, RejectReason::StorageUnavailable, now()));
+                            events
+                        }
+                    },
+                    OrderRequest::Cancel(cancel_request) => order_book_manager.cancel_order(cancel_request).await,
+                    OrderRequest::Replace(replace_request) => match order_book_manager.replace_order(replace_request.clone()).await {
+                        Ok(events) => events,
+                        Err(e) => {
+                            eprintln!("Failed to process replace request {}: {}", replace_request.id, e);
+                            let mut events = OrderEvents::default();
+                            events.ack = Some(new_ack(&replace_request.id, &replace_request.order_id, Some(RejectReason::StorageUnavailable), now()));
+                            events
                         }
                     },
-                    Ok(None) => {},
-                    Err(e) => {
-                        eprintln!("Failed to process order: {}", e);
-                    }
-                }
+                    OrderRequest::DepthSnapshot(depth_snapshot_request) => order_book_manager.depth_snapshot(depth_snapshot_request).await,
+                };
 
-                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+                order_event_publisher.publish(events).await;
             }
+            panic!("Order Book Manager stopped");
         }
     });
 
-    let order_book_manager_add_to_order_handle = tokio::spawn({
-        let order_book_manager = order_book_manager_clone.clone();
+    // every second, check the market-wide circuit breaker and start or end the call auctions and halts, then remove the DAY and GTD orders that have expired and publish an event for each of them
+    // The closing call ends at the session close, so it is uncrossed before the DAY orders expire
+    let order_book_manager_expire_handle = tokio::spawn({
+        let order_book_manager = order_book_manager.clone();
+        let order_event_publisher = order_event_publisher.clone();
         async move {
-            // Everytime receive an order, add to order book
-            while let Some(order_received) = oms_receiver.recv().await {
-                // Add the order to the order book
-                order_book_manager.add_to_orderbook(order_received).await.expect("Failed to add order to order book");
-
-                // if let Some(trade) = order_book_manager
-                //     .add_to_orderbook(order_received)
-                //     .await
-                //     .unwrap()
-                // {
-                //     // Send the trade to the trading side
-                //     if let Err(e) = mdg_sender.send(trade).await {
-                //         eprintln!("Failed to send trade via mdg_sender: {}", e);
-                //     }
-                // }
+            loop {
+                let events = order_book_manager.update_session_phase().await;
+                order_event_publisher.publish(events).await;
+
+                let events = order_book_manager.expire_orders().await;
+                order_event_publisher.publish(events).await;
+
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
             }
-            panic!("Order Book Manager stopped");
         }
     });
 
     // Catch the channel receiver and send to market data generator
-    let market_data_generator = MarketDataGenrator::new(REDIS_URL).await;
+    let market_data_generator = MarketDataGenrator::new(storage).await;
     let market_data_generator_handle = tokio::spawn(async move {
         market_data_generator.start(mdg_receiver, stock_sender).await;
 
@@ -92,16 +182,86 @@ async fn main() {
 
     let producer_handle = tokio::spawn(async move {
         // Produce stock prices from channel
+        // Every new price also goes to the order book manager, it can trigger stop orders
         while let Some(stock) = stock_receiver.recv().await {
+            let events = order_book_manager.update_last_price(&stock).await;
+            order_event_publisher.publish(events).await;
+
             producer.produce_stock(stock, TO_PRODUCE_TOPIC).await;
         }
     });
 
     let _ = tokio::join!(
         consumer_handle,
-        order_book_manager_process_order_handle,
-        order_book_manager_add_to_order_handle,
+        order_book_manager_handle,
+        order_book_manager_expire_handle,
         market_data_generator_handle,
         producer_handle
     );
 }
+
+struct OrderEventPublisher {
+    mdg_sender: Sender<Trade>,
+    trade_producer: JsonProducer<Trade>,
+    execution_report_producer: JsonProducer<ExecutionReport>,
+    order_request_ack_producer: JsonProducer<OrderRequestAck>,
+    order_expired_producer: JsonProducer<OrderExpired>,
+    depth_producer: JsonProducer<DepthMessage>,
+    auction_info_producer: JsonProducer<AuctionInfo>,
+    trading_status_producer: JsonProducer<TradingStatus>,
+}
+
+impl OrderEventPublisher {
+    async fn publish(&self, events: OrderEvents) {
+        // Halts first, so a subscriber knows trading stopped before it sees the last trades and depth of the halted stock
+        for trading_status in events.status {
+            println!("Trading status: {:?}", trading_status);
+            self.trading_status_producer.produce(&trading_status).await;
+        }
+
+        if let Some(ack) = events.ack {
+            self.order_request_ack_producer.produce(&ack).await;
+        }
+
+        // Publish every trade to the trade tape and send it to the market data generator, in the order they were made
+        for trade in events.trades {
+            println!("Trade: {:?}", trade);
+            self.trade_producer.produce(&trade).await;
+            if let Err(e) = self.mdg_sender.send(trade).await {
+                eprintln!("Failed to send trade via mdg_sender: {}", e);
+            }
+        }
+
+        for execution_report in events.execution_reports {
+            self.execution_report_producer.produce(&execution_report).await;
+        }
+
+        for order_expired in events.expired {
+            println!("Order expired: {:?}", order_expired);
+            self.order_expired_producer.produce(&order_expired).await;
+        }
+
+        for depth in events.depth {
+            self.depth_producer.produce(&depth).await;
+        }
+
+        for auction_info in events.auction {
+            self.auction_info_producer.produce(&auction_info).await;
+        }
+    }
+}
+
+// The market setup init writes to Redis, without a price every order would be rejected for an unknown symbol
+async fn initial_market() -> MemoryStorage {
+    let storage = MemoryStorage::new();
+    for (index, stock) in AVAILABLE_STOCKS.iter().enumerate() {
+        storage.set_price(stock, Price::from_f64(INITIAL_PRICE)).await.expect("Failed to set the initial price");
+        storage.set_sector(stock, sector(index));
+        storage.set_reference_data(stock, reference_data());
+    }
+    storage
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs()
+}
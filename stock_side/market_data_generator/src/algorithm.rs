@@ -1,4 +1,4 @@
-use common::models::{Order, Trade};
+use common::models::{DepthLevel, Order, Trade};
         
 // Algorithm 1: buy vs sell demand in term of share (number of buy/sell * their no. of share)
 pub fn algorithm_1(order: &(String, (Vec<Order>, Vec<Order>)), multiplier_vec: &mut Vec<f64>) {
@@ -86,6 +86,7 @@ pub fn algorithm_4(order:  &(String, (Vec<Order>, Vec<Order>)), multiplier_vec:
     let large_amount: u32 = (avg_buy_share + avg_sell_share) / 2;
 
     // Get the number of buy and sell orders that are larger than the large amount threashold
+    // The quantity of an iceberg order is its full quantity, the hidden part included, not just the peak shown in the order book
     let num_large_buy_orders = buy_orders.iter().filter(|o| o.quantity > large_amount).count();
     let num_large_sell_orders = sell_orders.iter().filter(|o| o.quantity > large_amount).count();
 
@@ -126,13 +127,10 @@ pub fn algorithm_5(order:  &(String, (Vec<Order>, Vec<Order>)), multiplier_vec:
 }
 
 // Algorithm 6: Order Book Skewness: skew of the order book (buy/sell orders) in term of price
-pub fn algorithm_6(order:  &(String, (Vec<Order>, Vec<Order>)), current_market_price: f64, multiplier_vec: &mut Vec<f64>) {
-    let buy_orders = &order.1 .0;
-    let sell_orders = &order.1 .1;
-
+pub fn algorithm_6(stock_symbol: &str, best_bid: Option<&DepthLevel>, best_ask: Option<&DepthLevel>, current_market_price: f64, multiplier_vec: &mut Vec<f64>) {
     // Get the highest buy and lowest sell price
-    let highest_buy_price = buy_orders.first().map(|o| o.price).unwrap_or(0.0);   // The highest buy price is the first buy order in the buy orders
-    let lowest_sell_price = sell_orders.first().map(|o| o.price).unwrap_or(0.0);    // The lowest sell price is the first sell order in the sell orders
+    let highest_buy_price = best_bid.map(|level| level.price.to_f64()).unwrap_or(0.0);   // The highest buy price is the top level of the bids
+    let lowest_sell_price = best_ask.map(|level| level.price.to_f64()).unwrap_or(0.0);    // The lowest sell price is the top level of the asks
 
     // Calculate the multiplier
     let imbalance = highest_buy_price - lowest_sell_price;
@@ -143,7 +141,7 @@ pub fn algorithm_6(order:  &(String, (Vec<Order>, Vec<Order>)), current_market_p
 
     let log_message = format!(
         "{}\nAlgorithm 6: Order Book Skewness: skew of the order book (buy/sell orders) in term of price\nStock symbol: {}\nThe rule is for every 10% skewness, the stock price will increase/decrease by 0.01\nHighest buy price: {}\nLowest sell price: {}\nImbalance: {}\nCurrent Market Price: {}\nSkewness: {}\nMultiplier: {}\n",
-        Local::now().format("%H.%M.%S %d-%m-%y").to_string(), &order.0, highest_buy_price, lowest_sell_price, imbalance, current_market_price, skewness, multiplier
+        Local::now().format("%H.%M.%S %d-%m-%y").to_string(), stock_symbol, highest_buy_price, lowest_sell_price, imbalance, current_market_price, skewness, multiplier
     );
     log_to_file(log_message)
 }
@@ -176,7 +174,7 @@ pub fn algorithm_7(order: &(String, (Vec<Order>, Vec<Order>)), sector: &str, mul
 // Algorithm For Active Trader: check the number of active traders and adjust the stock price based on the number of active traders
 pub fn algorithm_trade(trade_received: &Trade, stock_price: f64) -> f64 {
     // Update the stock price based on the trade price; 
-    let imbalance = (trade_received.quantity as f64) * (trade_received.price - stock_price);
+    let imbalance = (trade_received.quantity as f64) * (trade_received.price.to_f64() - stock_price);
     let multiplier = 1.0 + (imbalance / stock_price) * 0.01; // 0.01 is the sensitivity factor
     let new_price = (stock_price * multiplier * 10000.0).round() / 10000.0; // Round the new price to 4 decimal places
 
@@ -1,45 +1,31 @@
 use crate::algorithm;
 
 use common::models::{Order, Stock, Trade};
-use redis::{aio, AsyncCommands, RedisResult}; // RedisResult: Result type for Redis commands
-use serde_json::from_str; // Deserialize JSON string to struct
+use common::price::Price;
+use common::storage::{RestingOrders, Storage, StorageResult};
 use std::collections::HashMap;
 use tokio::sync::mpsc::{Receiver, Sender};
 use rand::Rng;
 use tokio::time::{sleep, Duration}; // to remove, check for computer resources used by this function
 
-pub struct MarketDataGenrator {
+pub struct MarketDataGenrator<S: Storage + Clone + 'static> {
     stocks: Vec<Stock>,
-    client: redis::Client,
-    // redis_conn: Arc<Mutex<aio::MultiplexedConnection>>,
+    // Clones share the backend, every task gets its own
+    storage: S,
 }
 
-impl MarketDataGenrator {
-    pub async fn new(redis_url: &str) -> Self {
-        println!("MarketDataGenrator: Connecting to Redis: {}", redis_url);
-        let client = redis::Client::open(redis_url).unwrap();
-        let mut redis_conn = client
-            .get_multiplexed_async_connection()
-            .await
-            .expect("MarketDataGenrator: Failed to connect to Redis");
-
-        println!("MarketDataGenrator: Connected to Redis");
-
-        // Fetch the initial list of stocks from Redis
-        let stocks = fetch_stocks(&mut redis_conn).await;
+impl<S: Storage + Clone + 'static> MarketDataGenrator<S> {
+    pub async fn new(storage: S) -> Self {
+        // Fetch the initial list of stocks from the storage
+        let stocks = fetch_stocks(&storage).await;
 
         Self {
             stocks,
-            client,
-            // redis_conn: Arc::new(Mutex::new(redis_conn)),
+            storage,
         }
     }
 
     pub async fn start(&self, mut mdg_receiver: Receiver<Trade>, stock_sender: Sender<Stock>) {
-        let redis_conn_task1 = &self.client.get_multiplexed_async_connection().await.expect("Failed to get redis connection");
-        let redis_conn_task2 = &self.client.get_multiplexed_async_connection().await.expect("Failed to get redis connection");
-        let redis_conn_task3 = &self.client.get_multiplexed_async_connection().await.expect("Failed to get redis connection");
-
         // Send inital stock prices
         for stock in &self.stocks {
             if let Err(e) = stock_sender.send(stock.clone()).await {
@@ -47,19 +33,16 @@ impl MarketDataGenrator {
             }
         }
 
-        // // Clone the data to move into the async task
-        // let redis_conn_clone = self.redis_conn.clone();
-
         // Task 1: Passive Update
         tokio::spawn({
-            let mut redis_conn_clone = redis_conn_task1.clone();
+            let storage = self.storage.clone();
             let stock_sender_clone = stock_sender.clone();
             async move {
                 loop {
                     println!("First task");
-                    let stocks = fetch_stocks(&mut redis_conn_clone).await;
-                    let orders = fetch_orders(&mut redis_conn_clone).await.unwrap();
-                    passive_update_stock_price(&mut redis_conn_clone, stocks, orders, stock_sender_clone.clone()).await;
+                    let stocks = fetch_stocks(&storage).await;
+                    let orders = fetch_orders(&storage).await.unwrap();
+                    passive_update_stock_price(&storage, stocks, orders, stock_sender_clone.clone()).await;
                     sleep(Duration::from_secs(1)).await; // To remove, check for computer resources used by this function
                 }
             }
@@ -67,27 +50,25 @@ impl MarketDataGenrator {
 
         // Task 2: Active Update of trade from channel
         tokio::spawn({
-            let mut redis_conn_clone = redis_conn_task2.clone();
+            let storage = self.storage.clone();
             let stock_sender_clone = stock_sender.clone();
             async move {
                 while let Some(trade_received) = mdg_receiver.recv().await {
                     println!("Second Task");
                     // Update the stock price based on the trade
-                    let stocks = fetch_stocks(&mut redis_conn_clone).await;
-                    active_update_stock_price(&mut redis_conn_clone, stocks, &trade_received, stock_sender_clone.clone()).await;
+                    let stocks = fetch_stocks(&storage).await;
+                    active_update_stock_price(&storage, stocks, &trade_received, stock_sender_clone.clone()).await;
                 }
             }
         });
 
         // Task 3: Sector Performance
         // tokio::spawn({
-        //     let mut redis_conn_clone = redis_conn_task3.clone();
+        //     let storage = self.storage.clone();
         //     async move {
-                // let mut conn = redis_conn_clone.lock().await;
-                let mut redis_conn_clone = redis_conn_task3.clone();
-                let stock_sector_string: RedisResult<Vec<(String, String)>> = redis_conn_clone.hgetall("stocks:sector").await;
+                let storage = &self.storage;
+                let stock_sector_string = storage.sectors().await;
 
-                // let stock_sector_string: RedisResult<Vec<(String, String)>> = redis_conn_clone.hgetall("stocks:sector").await;
                 let mut sector_hash: HashMap<String, f64> = HashMap::new();
                 
                 match stock_sector_string {
@@ -97,10 +78,8 @@ impl MarketDataGenrator {
                             let start_sector_hash = sector_hash.clone();
                             let mut cumulate_sector_hash: HashMap<String, Vec<f64>> = HashMap::new();
                             for (stock, sector) in stock_sector_result.clone() {
-                                // let mut conn = redis_conn_clone.lock().await;
-                                let price = redis_conn_clone.hget("stocks:prices", stock).await.unwrap();
-                                // drop(conn);
-                                cumulate_sector_hash.entry(sector).or_insert(Vec::new()).push(price);
+                                let price: Price = storage.price(&stock).await.unwrap().expect("Stock price not found");
+                                cumulate_sector_hash.entry(sector).or_default().push(price.to_f64());
                             }
                             for (sector, prices) in cumulate_sector_hash.clone() {
                                 let sum: f64 = prices.iter().sum();
@@ -117,25 +96,23 @@ impl MarketDataGenrator {
                             for (sector, price) in sector_hash.clone() {
                                 let start_price = start_sector_hash.get(&sector).unwrap();
                                 if (price - start_price).abs() / start_price > 0.0002564 {
-                                    // let mut conn = redis_conn_clone.lock().await;
-                                    let stock_sector_string: Vec<(String, String)> = redis_conn_clone.hgetall("stocks:sector").await.expect("Failed to fetch sector");
-                                    // drop(conn);
+                                    let stock_sector_string = storage.sectors().await.expect("Failed to fetch sector");
                                     
                                     // Update the stock price of all the other stocks in the sector
-                                    let stocks = fetch_stocks(&mut redis_conn_clone).await;
+                                    let stocks = fetch_stocks(storage).await;
 
                                     for (stock, sector_db) in stock_sector_string {
                                         if sector_db == sector {
                                             let stock: &Stock = stocks.iter().find(|s| s.symbol == stock).expect("Stock not found");
                                             let new_price = match price > *start_price {
                                                 true => {
-                                                    stock.price * rand::thread_rng().gen_range(1.0..1.1)
+                                                    stock.price.to_f64() * rand::thread_rng().gen_range(1.0..1.1)
                                                 }
                                                 false => {
-                                                    stock.price * rand::thread_rng().gen_range(0.9..1.0)
+                                                    stock.price.to_f64() * rand::thread_rng().gen_range(0.9..1.0)
                                                 }
                                             };
-                                            update_stock_price(&mut redis_conn_clone, stock, new_price, stock_sender.clone()).await.unwrap();
+                                            update_stock_price(storage, stock, new_price, stock_sender.clone()).await.unwrap();
                                         }
                                     }
                                 }
@@ -153,8 +130,8 @@ impl MarketDataGenrator {
     }
 }
 
-async fn passive_update_stock_price(
-    redis_conn: &mut aio::MultiplexedConnection,
+async fn passive_update_stock_price<S: Storage>(
+    storage: &S,
     latest_stock: Vec<Stock>,
     orders: HashMap<String, (Vec<Order>, Vec<Order>)>,
     stock_sender: Sender<Stock>,
@@ -175,45 +152,47 @@ async fn passive_update_stock_price(
         // Algorithm 5: Order Flow Momentum: check the recent time window of buy/sell orders, and if there is a momentum in the order flow, then increase/decrease the stock price perspectively
         algorithm::algorithm_5(&order, &mut multiplier_vec);
         // Algorithm 6: Order Book Skewness: skew of the order book (buy/sell orders) in term of price
-        algorithm::algorithm_6(&order, stock.price, &mut multiplier_vec);
+        // Only needs the best buy and sell price, so only the top level of each side is read
+        let (bids, asks) = storage.depth_levels(&order.0, 1).await.unwrap();
+        algorithm::algorithm_6(&order.0, bids.first(), asks.first(), stock.price.to_f64(), &mut multiplier_vec);
 
         // calculate the average of the multiplier_vec
         let sum: f64 = multiplier_vec.iter().sum();
         let multiplier: f64 = sum / multiplier_vec.len() as f64;
 
         // Update the stock price
-        let new_price = stock.price * multiplier;
-        update_stock_price(redis_conn, stock, new_price, stock_sender.clone()).await.unwrap();
+        let new_price = stock.price.to_f64() * multiplier;
+        update_stock_price(storage, stock, new_price, stock_sender.clone()).await.unwrap();
     }
 }
 
-async fn active_update_stock_price(
-    redis_conn: &mut aio::MultiplexedConnection,
+async fn active_update_stock_price<S: Storage>(
+    storage: &S,
     stocks: Vec<Stock>,
     trade_received: &Trade,
     stock_sender: Sender<Stock>,
 ) {
     let stock: &Stock = stocks.iter().find(|s| s.symbol == trade_received.stock_symbol).expect("Stock not found");
 
-    let new_price = algorithm::algorithm_trade(&trade_received, stock.price);
+    let new_price = algorithm::algorithm_trade(trade_received, stock.price.to_f64());
 
-    update_stock_price(redis_conn, stock, new_price, stock_sender.clone()).await.unwrap();
+    update_stock_price(storage, stock, new_price, stock_sender.clone()).await.unwrap();
 }
 
-async fn update_stock_price(
-    redis_conn: &mut aio::MultiplexedConnection,
+async fn update_stock_price<S: Storage>(
+    storage: &S,
     stock: &Stock,
     new_price: f64,
     stock_sender: Sender<Stock>,
-) -> RedisResult<()> {
+) -> StorageResult<()> {
     let updated_stock = Stock {
         symbol: stock.symbol.clone(),
-        price: ((new_price * 10000.0).round() / 10000.0).max(0.0001),
+        // The algorithms work in f64, the new price is rounded to the nearest 0.0001 and never goes below 0.0001
+        price: Price::from_f64(new_price).max(Price(1)),
     };
 
-    // Send the updated stock price to redis
-    // let mut conn = redis_conn.lock().await;
-    redis_conn.hset("stocks:prices", &updated_stock.symbol, updated_stock.price).await?;
+    // Save the updated stock price in the storage
+    storage.set_price(&updated_stock.symbol, updated_stock.price).await?;
 
     // Send the updated stock price to channel
     if let Err(e) = stock_sender.send(updated_stock.clone()).await {
@@ -223,9 +202,8 @@ async fn update_stock_price(
     Ok(())
 }
 
-async fn fetch_stocks(redis_conn: &mut aio::MultiplexedConnection) -> Vec<Stock> {
-    // let mut conn = redis_conn.lock().await;
-    let stocks_string: RedisResult<Vec<(String, String)>> = redis_conn.hgetall("stocks:prices").await;
+async fn fetch_stocks<S: Storage>(storage: &S) -> Vec<Stock> {
+    let stocks_string = storage.prices().await;
 
     let mut stocks: Vec<Stock> = Vec::new();
     match stocks_string {
@@ -233,7 +211,7 @@ async fn fetch_stocks(redis_conn: &mut aio::MultiplexedConnection) -> Vec<Stock>
             for (symbol, price) in stocks_result {
                 let stock = Stock {
                     symbol,
-                    price: price.parse().unwrap(),
+                    price,
                 };
                 stocks.push(stock);
             }
@@ -246,47 +224,11 @@ async fn fetch_stocks(redis_conn: &mut aio::MultiplexedConnection) -> Vec<Stock>
     stocks
 }
 
-// The return type of RedisResult<Vec<Order>, Vec<Order>> means there is a collection of each stock symbol's buy and sell orders
-async fn fetch_orders(
-    redis_conn: &mut aio::MultiplexedConnection,
-) -> RedisResult<HashMap<String, (Vec<Order>, Vec<Order>)>> {
-    // let mut conn = redis_conn.lock().await;
-    let keys: Vec<String> = {
-        let mut orders_book_iter: redis::AsyncIter<String> = redis_conn.scan_match("order_book:*").await.expect("Failed to scan order book");
-
-        let mut keys = Vec::new();
-        while let Some(key) = orders_book_iter.next_item().await {
-            keys.push(key);
-        }
-
-        keys
-    };
-
-    let mut all_stock_orders: HashMap<String, (Vec<Order>, Vec<Order>)> = HashMap::new();
-
-    for key in keys {
-        // Use a block to isolate the mutable borrow
-        let (buy_orders_string, sell_orders_string): (Option<String>, Option<String>) = {
-            redis_conn.hget(&key, &["buy_orders", "sell_orders"]).await?
-        };
-
-        // Deserialize the buy and sell orders into Vec<Order>
-        let buy_orders: Vec<Order> = match buy_orders_string {
-            Some(buy_orders) => from_str(&buy_orders).expect("Failed to deserialize buy orders"),
-            None => Vec::new(),
-        };
-
-        let sell_orders: Vec<Order> = match sell_orders_string {
-            Some(sell_orders) => from_str(&sell_orders).expect("Failed to deserialize sell orders"),
-            None => Vec::new(),
-        };
-
-        // Extract the stock symbol from the key
-        let stock_symbol = key.split(":").last().unwrap().to_string();
-
-        // Insert the buy and sell orders into the HashMap
-        all_stock_orders.insert(stock_symbol, (buy_orders, sell_orders));
-    }
-
-    Ok(all_stock_orders)
-}
+// The return type of StorageResult<Vec<Order>, Vec<Order>> means there is a collection of each stock symbol's buy and sell orders
+async fn fetch_orders<S: Storage>(
+    storage: &S,
+) -> StorageResult<RestingOrders> {
+    // Only the buy and sell orders, the stop orders waiting for their trigger are not in the order book yet
+    // Both sides of a book are read as one save of the order book manager, so they match
+    storage.resting_orders().await
+}
\ No newline at end of file
@@ -0,0 +1,32 @@
+// Rebuild the order books from the journal of the order book manager and check that replaying it gives the same trades
+// Usage: replay_journal <journal path>
+use order_management_system::replay::replay_journal;
+
+fn main() {
+    let Some(journal_path) = std::env::args().nth(1) else {
+        eprintln!("Usage: replay_journal <journal path>");
+        std::process::exit(2);
+    };
+
+    let replay = replay_journal(&journal_path).expect("Replay: Failed to read journal");
+
+    println!("Replay: {} events, {} trades replayed, {} mismatches", replay.events, replay.trades, replay.mismatches.len());
+    for mismatch in &replay.mismatches {
+        println!("Replay: {}", mismatch);
+    }
+    if let Some(engine) = &replay.engine {
+        for order_book in engine.order_books().values() {
+            println!(
+                "Replay: {} has {} buy, {} sell and {} stop orders",
+                order_book.symbol(),
+                order_book.buy_orders().len(),
+                order_book.sell_orders().len(),
+                order_book.stop_orders().len()
+            );
+        }
+    }
+
+    if !replay.mismatches.is_empty() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,70 @@
+use common::models::{AckStatus, ExecType, ExecutionReport, Order, OrderRequestAck, RejectReason};
+use common::price::Price;
+
+// Execution report of the order in its current state
+pub fn new_execution_report(order: &Order, exec_type: ExecType, timestamp: u64) -> ExecutionReport {
+    ExecutionReport {
+        order_id: order.id.clone(),
+        stock_symbol: order.stock_symbol.clone(),
+        order_type: order.order_type,
+        exec_type,
+        price: order.price,
+        last_quantity: 0,
+        last_price: Price::ZERO,
+        leaves_quantity: order.quantity,
+        cumulative_quantity: order.filled_quantity,
+        average_price: order.average_price,
+        stop_price: order.stop_price,
+        reason: None,
+        reject_reason: None,
+        timestamp,
+    }
+}
+
+// Execution report of the order in its current state with a reason, for cancels and rejects
+pub fn new_execution_report_with_reason(order: &Order, exec_type: ExecType, reason: &str, timestamp: u64) -> ExecutionReport {
+    ExecutionReport {
+        reason: Some(reason.to_string()),
+        ..new_execution_report(order, exec_type, timestamp)
+    }
+}
+
+// Rejected execution report of an order that never made it into the order book
+pub fn new_rejected_execution_report(order: &Order, reject_reason: RejectReason, timestamp: u64) -> ExecutionReport {
+    ExecutionReport {
+        reject_reason: Some(reject_reason),
+        ..new_execution_report_with_reason(order, ExecType::Rejected, &reject_reason.to_string(), timestamp)
+    }
+}
+
+// Answer to a cancel or replace request, accepted when there is no reject reason
+pub fn new_ack(request_id: &str, order_id: &str, reject_reason: Option<RejectReason>, now: u64) -> OrderRequestAck {
+    OrderRequestAck {
+        request_id: request_id.to_string(),
+        order_id: order_id.to_string(),
+        status: match reject_reason {
+            Some(_) => AckStatus::Rejected,
+            None => AckStatus::Accepted,
+        },
+        reason: reject_reason.map(|reason| reason.to_string()),
+        reject_reason,
+        timestamp: now,
+    }
+}
+
+// Record a fill on the order and return its fill report, the order quantity must already be reduced by the fill
+// The average price is rounded to the nearest 0.0001, the filled value is kept in i128 so it can't overflow
+pub fn record_fill(order: &mut Order, quantity: u32, price: Price, timestamp: u64) -> ExecutionReport {
+    let filled_value = order.average_price.0 as i128 * order.filled_quantity as i128 + price.0 as i128 * quantity as i128;
+    order.filled_quantity += quantity;
+    let filled_quantity = order.filled_quantity as i128;
+    order.average_price = Price(((2 * filled_value + filled_quantity) / (2 * filled_quantity)) as i64);
+
+    let exec_type = if order.quantity == 0 { ExecType::Fill } else { ExecType::PartialFill };
+
+    ExecutionReport {
+        last_quantity: quantity,
+        last_price: price,
+        ..new_execution_report(order, exec_type, timestamp)
+    }
+}
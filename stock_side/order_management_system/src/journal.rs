@@ -0,0 +1,123 @@
+use common::models::{CancelRequest, Order, ReplaceRequest, Stock, Trade};
+use common::storage::SavedOrderBook;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::matching_engine::EngineSettings;
+
+// Everything that went into the matching engine, and the trades that came out of it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum JournalEvent {
+    // The order book manager started with these order books (as loaded from the storage), settings, next sequence number
+    // and end of the market-wide halt
+    // restored when the storage was replaced by setup init or restore before, the order books don't follow on from the entries before
+    Started {
+        settings: EngineSettings,
+        next_sequence: u64,
+        #[serde(default)]
+        market_halted_until: u64,
+        order_books: Vec<SavedOrderBook>,
+        #[serde(default)]
+        restored: bool,
+    },
+    // Accepted order as it was given to the matching engine, before it got its sequence number
+    NewOrder(Order),
+    Cancel(CancelRequest),
+    Replace(ReplaceRequest),
+    // Price from the market data generator, journaled when it changed something or ahead of the next entry after it
+    LastPrice(Stock),
+    // Session phase update or expiry check that changed something, only the time of it is needed
+    SessionPhase,
+    Expire,
+    // Trade made by the event before it
+    Trade(Trade),
+}
+
+// One line of the journal, sequence goes up by one for every entry and timestamp is the time the matching engine was given
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JournalEntry {
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub event: JournalEvent,
+}
+
+// Append-only journal file, one JSON entry per line
+// Entries are flushed to disk before the order books are saved in the storage, so the journal is never behind it
+pub struct Journal {
+    file: File,
+    next_sequence: u64,
+}
+
+impl Journal {
+    // Open the journal to append to it, new entries continue after the last complete one
+    // A last line cut off by a crash is removed first, the event in it never reached the storage either
+    pub fn open(path: &str) -> io::Result<Self> {
+        let (entries, complete_length) = read_entries(path)?;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() > complete_length {
+            println!("Journal: Removing an incomplete last entry from {}", path);
+            file.set_len(complete_length)?;
+        }
+
+        let next_sequence = entries.last().map_or(1, |entry| entry.sequence + 1);
+        println!("Journal: Opened {}, {} entries so far", path, entries.len());
+
+        Ok(Self { file, next_sequence })
+    }
+
+    // Write the events of one request as consecutive entries and wait until they are on disk
+    pub fn append(&mut self, timestamp: u64, events: Vec<JournalEvent>) -> io::Result<()> {
+        let mut lines = String::new();
+        for event in events {
+            let entry = JournalEntry { sequence: self.next_sequence, timestamp, event };
+            lines.push_str(&serde_json::to_string(&entry).expect("Failed to serialize journal entry"));
+            lines.push('\n');
+            self.next_sequence += 1;
+        }
+
+        self.file.write_all(lines.as_bytes())?;
+        self.file.sync_data()
+    }
+}
+
+// Every complete entry of the journal in order, empty if there is no journal yet
+pub fn read_journal(path: &str) -> io::Result<Vec<JournalEntry>> {
+    read_entries(path).map(|(entries, _)| entries)
+}
+
+// Entries and the length in bytes of the complete lines, only the last line may be incomplete
+fn read_entries(path: &str) -> io::Result<(Vec<JournalEntry>, u64)> {
+    if !Path::new(path).exists() {
+        return Ok((Vec::new(), 0));
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut entries: Vec<JournalEntry> = Vec::new();
+    let mut complete_length: u64 = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let length = reader.read_line(&mut line)?;
+        if length == 0 {
+            break;
+        }
+
+        match serde_json::from_str::<JournalEntry>(line.trim_end()) {
+            Ok(entry) if line.ends_with('\n') => {
+                entries.push(entry);
+                complete_length += length as u64;
+            }
+            // Only the last line can be cut off by a crash
+            _ if reader.fill_buf()?.is_empty() => break,
+            // A broken line in the middle is not from a crash, stop instead of guessing
+            _ => {
+                let message = format!("Invalid journal entry after sequence {}", entries.last().map_or(0, |entry| entry.sequence));
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+    }
+
+    Ok((entries, complete_length))
+}
@@ -1 +1,7 @@
-pub mod order_book_manager;
\ No newline at end of file
+pub mod execution_report;
+pub mod journal;
+pub mod matching_engine;
+pub mod order_book;
+pub mod order_book_manager;
+pub mod replay;
+pub mod validation;
@@ -0,0 +1,567 @@
+use common::models::{
+    AuctionInfo, CancelRequest, DepthMessage, DepthSnapshot, DepthSnapshotRequest, ExecType, ExecutionReport, Order,
+    OrderExpired, OrderKind, OrderRequestAck, PostOnly, ReferenceData, RejectReason, ReplaceRequest, SessionPhase, Stock, TimeInForce,
+    Trade, TradingStatus,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::execution_report::{new_ack, new_execution_report, new_execution_report_with_reason};
+use crate::order_book::{trailing_stop_level, MatchResult, OrderBook, SelfTradePrevention};
+use crate::validation::validate_replace;
+
+// Number of price levels per side in the depth feed
+const DEPTH_LEVELS: usize = 10;
+
+// Seconds in a day, the session times are seconds after midnight UTC
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// Call auction every trading day from start to end (seconds after midnight UTC), the order books are uncrossed at end
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct CallAuction {
+    pub phase: SessionPhase,
+    pub start: u64,
+    pub end: u64,
+}
+
+// Trading day from open to close (seconds after midnight UTC), the market is closed the rest of the day
+// The call auctions are inside of it, the pre-open call usually starts at open and the pre-close call ends at close
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct TradingHours {
+    pub open: u64,
+    pub close: u64,
+}
+
+// When trading is halted, the moves are from the reference price of each order book (the price of its last auction)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct CircuitBreakers {
+    // Price band of a stock that has none in stocks:price_band, 0.1 = halt the stock when it moves 10% either way
+    pub price_band: f64,
+    // Halt every stock when they moved this much on average, 0.07 = 7% either way
+    pub market_wide_move: f64,
+    // Seconds a halt lasts before the reopening auction
+    pub cool_down: u64,
+}
+
+// Everything the matching depends on besides the orders, the same settings give the same trades
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EngineSettings {
+    // Used by every order book when two orders of the same account would trade with each other
+    pub self_trade_prevention: SelfTradePrevention,
+    // Tick size, lot size, minimum quantity and price band of every stock that has them
+    pub reference_data: HashMap<String, ReferenceData>,
+    // Pre-open and pre-close calls, trading is continuous the rest of the trading hours (and always if there are none)
+    pub call_auctions: Vec<CallAuction>,
+    // None to trade around the clock
+    #[serde(default)]
+    pub trading_hours: Option<TradingHours>,
+    pub circuit_breakers: CircuitBreakers,
+}
+
+// Everything that came out of handling one request, for the caller to publish
+#[derive(Debug, Default)]
+pub struct OrderEvents {
+    // Every trade made, in the order they happened
+    pub trades: Vec<Trade>,
+    // Lifecycle events of every order involved, in the order they happened
+    pub execution_reports: Vec<ExecutionReport>,
+    // Answer to a cancel or replace request
+    pub ack: Option<OrderRequestAck>,
+    pub expired: Vec<OrderExpired>,
+    // Depth updates of the order books that changed, or a requested snapshot
+    pub depth: Vec<DepthMessage>,
+    // Indicative price and imbalance of the order books in a call auction that changed
+    pub auction: Vec<AuctionInfo>,
+    // New trading phase of the order books that were halted, reopened or went in or out of a call auction
+    pub status: Vec<TradingStatus>,
+    // Stock symbols of the order books whose orders changed, for the order book manager to save
+    pub(crate) changed_order_books: Vec<String>,
+}
+
+// The order books of every stock and all the matching, entirely in memory and without a clock of its own
+// Every method takes the time it runs at, so the same requests at the same times always give the same trades (see the journal)
+// The books are kept by stock symbol in a BTreeMap so going through all of them always happens in the same order
+pub struct MatchingEngine {
+    order_books: BTreeMap<String, OrderBook>,
+    // Sequence number given to the next accepted order, only increases
+    next_sequence: u64,
+    settings: EngineSettings,
+    // When the market-wide halt ends, 0 if the market was never halted
+    market_halted_until: u64,
+}
+
+impl MatchingEngine {
+    // Take over the order books and the market-wide halt as they were saved, with the settings applied to each book
+    pub fn new(order_books: BTreeMap<String, OrderBook>, next_sequence: u64, market_halted_until: u64, settings: EngineSettings, now: u64) -> Self {
+        let mut engine = Self {
+            order_books: BTreeMap::new(),
+            next_sequence,
+            settings,
+            market_halted_until,
+        };
+        for (stock_symbol, order_book) in order_books {
+            let order_book = engine.with_settings(order_book, now);
+            engine.order_books.insert(stock_symbol, order_book);
+        }
+        engine
+    }
+
+    pub fn order_books(&self) -> &BTreeMap<String, OrderBook> {
+        &self.order_books
+    }
+
+    pub fn settings(&self) -> &EngineSettings {
+        &self.settings
+    }
+
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    // Sequence number of the last accepted order
+    pub fn last_sequence(&self) -> u64 {
+        self.next_sequence - 1
+    }
+
+    // When the market-wide halt ends, 0 if the market was never halted
+    pub fn market_halted_until(&self) -> u64 {
+        self.market_halted_until
+    }
+
+    pub fn reference_data(&self, stock_symbol: &str) -> Option<&ReferenceData> {
+        self.settings.reference_data.get(stock_symbol)
+    }
+
+    // Last checks of a new order that need its order book, done before it is accepted
+    // A trailing stop starts trailing from the last price of its stock, the stop price sent with it is only used if there is none yet
+    // It is refused if its offset from the last price leaves no positive trigger level
+    pub fn prepare_order(&self, order: &mut Order, now: u64) -> Result<(), RejectReason> {
+        let order_book = self.order_books.get(&order.stock_symbol);
+
+        if order.kind == OrderKind::TrailingStop {
+            match order_book.and_then(|order_book| order_book.last_price()) {
+                Some(last_price) => match trailing_stop_level(order, last_price) {
+                    Some(level) => order.stop_price = Some(level),
+                    None => return Err(RejectReason::InvalidTrailingOffset),
+                },
+                None if order.stop_price.is_none() => return Err(RejectReason::NoLastPrice),
+                None => {}
+            }
+        }
+
+        // During a call auction, a halt or while the market is closed only orders that can wait in the book for the uncross are accepted
+        let phase = match order_book {
+            Some(order_book) => order_book.phase(),
+            None => self.starting_phase(now),
+        };
+        let can_wait = order.kind != OrderKind::Market
+            && !matches!(order.time_in_force, TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill);
+        match phase {
+            SessionPhase::Continuous => {}
+            _ if can_wait => {}
+            SessionPhase::Closed => return Err(RejectReason::MarketClosed),
+            _ => return Err(RejectReason::NotAllowedInAuction),
+        }
+
+        Ok(())
+    }
+
+    // Match the incoming order against its order book, any remaining quantity rests or is cancelled depending on its kind and time in force
+    // Stop and stop-limit orders wait in the trigger book instead, and any stop triggered by the trades of this order is matched right after
+    // The order must have passed the validation and prepare_order, it gets its sequence number here
+    pub fn accept_order(&mut self, mut order: Order, now: u64) -> OrderEvents {
+        let mut events = OrderEvents::default();
+
+        order.sequence = self.take_sequence();
+        events.execution_reports.push(new_execution_report(&order, ExecType::New, now));
+
+        let stock_symbol = order.stock_symbol.clone();
+        let mut order_book = match self.order_books.remove(&stock_symbol) {
+            Some(order_book) => order_book,
+            None => self.with_settings(OrderBook::new(&stock_symbol), now),
+        };
+        let depth_before = order_book.depth_snapshot(DEPTH_LEVELS, now);
+        let result = order_book.match_order(order);
+        events.add_match_result(result, now);
+        self.settle_order_book(&mut order_book, &mut events, now);
+
+        events.add_depth_update(&mut order_book, &depth_before, now);
+        events.changed_order_books.push(stock_symbol.clone());
+        self.order_books.insert(stock_symbol, order_book);
+        events
+    }
+
+    // Remove a resting order from its order book, answered with an ack or reject
+    pub fn cancel_order(&mut self, request: CancelRequest, now: u64) -> OrderEvents {
+        let mut events = OrderEvents::default();
+
+        let Some(mut order_book) = self.order_books.remove(&request.stock_symbol) else {
+            events.ack = Some(new_ack(&request.id, &request.order_id, Some(RejectReason::OrderNotFound), now));
+            return events;
+        };
+        let depth_before = order_book.depth_snapshot(DEPTH_LEVELS, now);
+
+        match order_book.cancel(&request.order_id) {
+            Some(order) => {
+                events.ack = Some(new_ack(&request.id, &request.order_id, None, now));
+                events.execution_reports.push(new_execution_report_with_reason(&order, ExecType::Cancelled, "Cancelled by request", now));
+                self.settle_order_book(&mut order_book, &mut events, now);
+                events.add_depth_update(&mut order_book, &depth_before, now);
+                events.changed_order_books.push(request.stock_symbol.clone());
+            }
+            None => {
+                events.ack = Some(new_ack(&request.id, &request.order_id, Some(RejectReason::OrderNotFound), now));
+            }
+        }
+
+        self.order_books.insert(request.stock_symbol, order_book);
+        events
+    }
+
+    // Cancel/replace a resting order, answered with an ack or reject
+    // The new price must have passed validate_replace_price, the stock price it needs is not part of the engine
+    // Lowering the quantity at the same price is done in place and keeps the time priority
+    // Any other change takes the order out of the book and matches it again as a new arrival with a new sequence
+    pub fn replace_order(&mut self, request: ReplaceRequest, now: u64) -> OrderEvents {
+        let mut events = OrderEvents::default();
+
+        if let Err(reject_reason) = validate_replace(&request, self.reference_data(&request.stock_symbol)) {
+            events.ack = Some(new_ack(&request.id, &request.order_id, Some(reject_reason), now));
+            return events;
+        }
+
+        let existing = self.order_books.get(&request.stock_symbol).and_then(|order_book| order_book.get(&request.order_id));
+        let Some(existing) = existing.cloned() else {
+            events.ack = Some(new_ack(&request.id, &request.order_id, Some(RejectReason::OrderNotFound), now));
+            return events;
+        };
+        let mut order_book = self.order_books.remove(&request.stock_symbol).expect("Order was just found in the order book");
+
+        events.ack = Some(new_ack(&request.id, &request.order_id, None, now));
+        let depth_before = order_book.depth_snapshot(DEPTH_LEVELS, now);
+
+        if request.price == existing.price && request.quantity <= existing.quantity {
+            order_book.reduce_quantity(&request.order_id, request.quantity);
+            let replaced = order_book.get(&request.order_id).expect("Order was just reduced in the order book");
+            events.execution_reports.push(new_execution_report(replaced, ExecType::Replaced, now));
+        } else {
+            let mut order = order_book.cancel(&request.order_id).expect("Order was just found in the order book");
+            order.quantity = request.quantity;
+            order.price = request.price;
+            order.visible_quantity = 0;
+            order.sequence = self.take_sequence();
+            events.execution_reports.push(new_execution_report(&order, ExecType::Replaced, now));
+
+            let result = order_book.match_order(order);
+            events.add_match_result(result, now);
+        }
+        self.settle_order_book(&mut order_book, &mut events, now);
+
+        events.add_depth_update(&mut order_book, &depth_before, now);
+        events.changed_order_books.push(request.stock_symbol.clone());
+        self.order_books.insert(request.stock_symbol, order_book);
+        events
+    }
+
+    // Latest price of a stock from the market data generator
+    // Trailing stop orders follow it (reported as restated), then the stop orders it reaches are triggered and matched
+    pub fn update_last_price(&mut self, stock: &Stock, now: u64) -> OrderEvents {
+        let mut events = OrderEvents::default();
+
+        let Some(mut order_book) = self.order_books.remove(&stock.symbol) else {
+            return events;
+        };
+
+        let depth_before = order_book.depth_snapshot(DEPTH_LEVELS, now);
+        let moved = order_book.set_last_price(stock.price);
+        for order in &moved {
+            events.execution_reports.push(new_execution_report(order, ExecType::Restated, now));
+        }
+        let settled = self.settle_order_book(&mut order_book, &mut events, now);
+        if !moved.is_empty() || settled {
+            events.add_depth_update(&mut order_book, &depth_before, now);
+            events.changed_order_books.push(stock.symbol.clone());
+        }
+
+        self.order_books.insert(stock.symbol.clone(), order_book);
+        events
+    }
+
+    // Move every order book to the session phase of the current time, called every second
+    // The market-wide circuit breaker is checked first, a halted book stays halted until its cool-down is over
+    // A book entering a call auction publishes its first indicative price, a book leaving one (or a halt) is uncrossed and trades continuously again
+    // At the close the closing call is uncrossed and the book waits for the opening call, orders sent while closed are uncrossed at the open
+    pub fn update_session_phase(&mut self, now: u64) -> OrderEvents {
+        let mut events = OrderEvents::default();
+        let mut order_books = std::mem::take(&mut self.order_books);
+
+        if self.market_halted_until <= now {
+            let price_moves: Vec<f64> = order_books
+                .values()
+                .filter(|order_book| !order_book.in_call_auction())
+                .filter_map(|order_book| order_book.price_move())
+                .collect();
+            let market_move = price_moves.iter().sum::<f64>() / price_moves.len().max(1) as f64;
+
+            if market_move.abs() > self.settings.circuit_breakers.market_wide_move {
+                let until = now + self.settings.circuit_breakers.cool_down;
+                self.market_halted_until = until;
+                let reason = format!("Market-wide circuit breaker, stocks moved {:.2}% on average", market_move * 100.0);
+                for order_book in order_books.values_mut() {
+                    halt_order_book(order_book, &mut events, until, &reason, now);
+                    events.changed_order_books.push(order_book.symbol().to_string());
+                }
+            }
+        }
+
+        let scheduled_phase = session_phase(&self.settings, now);
+        for order_book in order_books.values_mut() {
+            let phase = match order_book.halted_until() {
+                Some(halted_until) if now < halted_until => SessionPhase::Halted,
+                _ => scheduled_phase,
+            };
+            if order_book.phase() == phase {
+                continue;
+            }
+
+            let depth_before = order_book.depth_snapshot(DEPTH_LEVELS, now);
+            order_book.set_phase(phase);
+            events.status.push(new_trading_status(order_book, None, now));
+            if matches!(phase, SessionPhase::Continuous | SessionPhase::Closed) {
+                let result = order_book.uncross();
+                events.add_match_result(result, now);
+                // The band starts again from the auction price, so the move that caused a halt doesn't halt the book again
+                order_book.reset_reference_price();
+                self.settle_order_book(order_book, &mut events, now);
+            } else {
+                events.auction.push(order_book.auction_info(now));
+            }
+
+            events.add_depth_update(order_book, &depth_before, now);
+            events.changed_order_books.push(order_book.symbol().to_string());
+        }
+
+        self.order_books = order_books;
+        events
+    }
+
+    // Remove every DAY and GTD order that has expired from all order books, with an expiry event and execution report for each removed order
+    pub fn expire_orders(&mut self, now: u64) -> OrderEvents {
+        let mut events = OrderEvents::default();
+        let mut order_books = std::mem::take(&mut self.order_books);
+
+        for order_book in order_books.values_mut() {
+            let depth_before = order_book.depth_snapshot(DEPTH_LEVELS, now);
+            let expired = order_book.remove_expired(now);
+            if expired.is_empty() {
+                continue;
+            }
+
+            for order in expired {
+                events.execution_reports.push(new_execution_report(&order, ExecType::Expired, now));
+                events.expired.push(OrderExpired {
+                    order_id: order.id,
+                    stock_symbol: order.stock_symbol,
+                    order_type: order.order_type,
+                    time_in_force: order.time_in_force,
+                    unfilled_quantity: order.quantity,
+                    expire_time: order.expire_time.unwrap_or(now),
+                    timestamp: now,
+                });
+            }
+
+            self.settle_order_book(order_book, &mut events, now);
+            events.add_depth_update(order_book, &depth_before, now);
+            events.changed_order_books.push(order_book.symbol().to_string());
+        }
+
+        self.order_books = order_books;
+        events
+    }
+
+    // Answer a depth snapshot request with the top levels of the order book, empty if the stock has no order book yet
+    pub fn depth_snapshot(&self, request: &DepthSnapshotRequest, now: u64) -> OrderEvents {
+        let mut events = OrderEvents::default();
+
+        let snapshot = match self.order_books.get(&request.stock_symbol) {
+            Some(order_book) => order_book.depth_snapshot(DEPTH_LEVELS, now),
+            None => OrderBook::new(&request.stock_symbol).depth_snapshot(DEPTH_LEVELS, now),
+        };
+        events.depth.push(DepthMessage::Snapshot(snapshot));
+
+        events
+    }
+
+    fn take_sequence(&mut self) -> u64 {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        sequence
+    }
+
+    // Bring the order book to rest after it changed: trigger the stop orders the last price reached and reprice the pegged orders
+    // Repeated while repricing makes trades, those can trigger more stop orders and move the pegs again
+    // Returns false if nothing was triggered, repriced or halted
+    fn settle_order_book(&mut self, order_book: &mut OrderBook, events: &mut OrderEvents, now: u64) -> bool {
+        let mut changed = false;
+
+        loop {
+            changed |= self.release_triggered_stops(order_book, events, now);
+
+            let results = order_book.reprice_pegged();
+            changed |= !results.is_empty();
+            let traded = results.iter().any(|result| !result.trades.is_empty());
+            for result in results {
+                events.add_match_result(result, now);
+            }
+
+            if !traded {
+                changed |= self.check_price_band(order_book, events, now);
+                return changed;
+            }
+        }
+    }
+
+    // Match every stop order the last price has reached as a new arrival with a new sequence
+    // Repeated until nothing triggers anymore, as the trades of a triggered stop can move the price through other stops
+    // Returns false if no stop order was triggered
+    fn release_triggered_stops(&mut self, order_book: &mut OrderBook, events: &mut OrderEvents, now: u64) -> bool {
+        let mut any_triggered = false;
+
+        loop {
+            let triggered = order_book.take_triggered_stops();
+            if triggered.is_empty() {
+                return any_triggered;
+            }
+            any_triggered = true;
+
+            for mut order in triggered {
+                order.sequence = self.take_sequence();
+                events.execution_reports.push(new_execution_report(&order, ExecType::Triggered, now));
+
+                let result = order_book.match_order(order);
+                events.add_match_result(result, now);
+            }
+        }
+    }
+
+    // Halt the order book if its price left the price band, returns false if it is still inside
+    // The trade or market data price that left the band has already happened, the halt stops the ones after it
+    fn check_price_band(&self, order_book: &mut OrderBook, events: &mut OrderEvents, now: u64) -> bool {
+        if order_book.in_call_auction() {
+            return false;
+        }
+        let Some(price_move) = order_book.price_move() else {
+            return false;
+        };
+        let price_band = self
+            .reference_data(order_book.symbol())
+            .and_then(|reference_data| reference_data.price_band)
+            .unwrap_or(self.settings.circuit_breakers.price_band);
+        if price_move.abs() <= price_band {
+            return false;
+        }
+
+        let reason = format!(
+            "Price moved {:.2}% from {}, outside its {:.2}% band",
+            price_move * 100.0,
+            order_book.reference_price().expect("Reference price was just set"),
+            price_band * 100.0
+        );
+        halt_order_book(order_book, events, now + self.settings.circuit_breakers.cool_down, &reason, now);
+        true
+    }
+
+    // The order book with the settings of the engine, in the phase it should be in now
+    // A book that was saved while halted stays halted, update_session_phase reopens it with an auction once the halt is over
+    fn with_settings(&self, mut order_book: OrderBook, now: u64) -> OrderBook {
+        order_book.set_self_trade_prevention(self.settings.self_trade_prevention);
+        if let Some(reference_data) = self.reference_data(order_book.symbol()) {
+            order_book.set_tick_size(reference_data.tick_size);
+        }
+        match self.starting_phase(now) {
+            SessionPhase::Halted => order_book.halt(self.market_halted_until),
+            _ if order_book.halted_until().is_some() => {}
+            phase => order_book.set_phase(phase),
+        }
+        order_book
+    }
+
+    // Phase of an order book created now: halted during a market-wide halt, otherwise the phase of the schedule
+    fn starting_phase(&self, now: u64) -> SessionPhase {
+        if self.market_halted_until > now {
+            return SessionPhase::Halted;
+        }
+        session_phase(&self.settings, now)
+    }
+}
+
+impl OrderEvents {
+    // Take the trades and fill reports of a match, and report the new price and the cancelled remainder if there are
+    fn add_match_result(&mut self, result: MatchResult, now: u64) {
+        if let Some(repriced) = result.repriced {
+            self.execution_reports.push(new_execution_report(&repriced, ExecType::Restated, now));
+        }
+
+        self.trades.extend(result.trades);
+        self.execution_reports.extend(result.execution_reports);
+
+        if let Some(cancelled) = result.cancelled {
+            let reason = match (cancelled.kind, cancelled.time_in_force) {
+                _ if cancelled.post_only == Some(PostOnly::Reject) => "Post-only order would have taken liquidity",
+                (_, TimeInForce::FillOrKill) => "Fill or kill order could not be filled completely",
+                (OrderKind::Market, _) => "No more liquidity for market order",
+                _ => "Unfilled remainder of immediate or cancel order",
+            };
+            self.execution_reports.push(new_execution_report_with_reason(&cancelled, ExecType::Cancelled, reason, now));
+        }
+    }
+
+    // Publish the top levels that changed since depth_before was taken, if any
+    // During a call auction the indicative price and imbalance move with the book, so they are published with the depth update
+    fn add_depth_update(&mut self, order_book: &mut OrderBook, depth_before: &DepthSnapshot, now: u64) {
+        if let Some(depth_update) = order_book.depth_update(depth_before, DEPTH_LEVELS, now) {
+            self.depth.push(DepthMessage::Update(depth_update));
+            if order_book.in_call_auction() {
+                self.auction.push(order_book.auction_info(now));
+            }
+        }
+    }
+}
+
+// Stop matching until the reopening auction, orders keep coming in and wait for it like in a call auction
+fn halt_order_book(order_book: &mut OrderBook, events: &mut OrderEvents, until: u64, reason: &str, now: u64) {
+    order_book.halt(until);
+    events.status.push(new_trading_status(order_book, Some(reason), now));
+    events.auction.push(order_book.auction_info(now));
+}
+
+fn new_trading_status(order_book: &OrderBook, reason: Option<&str>, now: u64) -> TradingStatus {
+    TradingStatus {
+        stock_symbol: order_book.symbol().to_string(),
+        phase: order_book.phase(),
+        reason: reason.map(|reason| reason.to_string()),
+        resume_time: order_book.halted_until(),
+        timestamp: now,
+    }
+}
+
+// Closed outside the trading hours, otherwise the phase of the call auction running at now, continuous if none is
+fn session_phase(settings: &EngineSettings, now: u64) -> SessionPhase {
+    let time_of_day = now % SECONDS_PER_DAY;
+    if let Some(trading_hours) = settings.trading_hours {
+        // The trading day can go past midnight UTC
+        let open = match trading_hours.open <= trading_hours.close {
+            true => trading_hours.open <= time_of_day && time_of_day < trading_hours.close,
+            false => trading_hours.open <= time_of_day || time_of_day < trading_hours.close,
+        };
+        if !open {
+            return SessionPhase::Closed;
+        }
+    }
+
+    settings
+        .call_auctions
+        .iter()
+        .find(|call_auction| call_auction.start <= time_of_day && time_of_day < call_auction.end)
+        .map_or(SessionPhase::Continuous, |call_auction| call_auction.phase)
+}
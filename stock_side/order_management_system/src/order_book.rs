@@ -0,0 +1,931 @@
+use common::models::{
+    AuctionInfo, DepthChange, DepthLevel, DepthSnapshot, DepthUpdate, ExecType, ExecutionReport, Order, OrderKind, OrderType, PegReference,
+    PostOnly, SessionPhase, TimeInForce, Trade, TrailingOffset,
+};
+use common::order_book_layout::shown_quantity;
+use common::price::Price;
+use common::storage::SavedOrderBook;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::execution_report::{new_execution_report_with_reason, record_fill};
+
+// Tick size of a stock without reference data
+const DEFAULT_TICK_SIZE: Price = Price(100);   // 0.01
+
+// What happens when an incoming order would trade with a resting order of the same account
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelfTradePrevention {
+    // Cancel what is left of the incoming order
+    #[default]
+    CancelNewest,
+    // Cancel the resting order and keep matching the incoming order
+    CancelOldest,
+    CancelBoth,
+    // Take the smaller quantity off both orders without trading, the order that reaches 0 is cancelled
+    Decrement,
+}
+
+// Outcome of matching one incoming order
+#[derive(Debug, Default)]
+pub struct MatchResult {
+    // Every trade made, in the order they happened
+    pub trades: Vec<Trade>,
+    // Fill reports of the incoming order and the resting orders it traded with, in the order they happened
+    pub execution_reports: Vec<ExecutionReport>,
+    // The unfilled remainder of the incoming order when it could not rest in the book (market, IOC and FOK orders)
+    pub cancelled: Option<Order>,
+    // The incoming order after it got a new price, when it is pegged or a post-only order that would have taken liquidity
+    pub repriced: Option<Order>,
+}
+
+// Price a call auction would uncross at, with the quantity that would trade and what would be left over
+struct Equilibrium {
+    price: Price,
+    matched_quantity: u32,
+    imbalance_quantity: u32,
+    imbalance_side: Option<OrderType>,
+}
+
+// In-memory order book of a single stock symbol
+// Each side is a map of price level -> orders at that price, the orders in a level are kept in sequence order (FIFO)
+// so the priority is strictly price first, then the sequence number assigned on acceptance
+pub struct OrderBook {
+    symbol: String,
+    // Buy side, Reverse so that the highest price is the first level
+    bids: BTreeMap<Reverse<Price>, VecDeque<Order>>,
+    // Sell side, the lowest price is the first level
+    asks: BTreeMap<Price, VecDeque<Order>>,
+    // Side and price level of every resting order by order id, so an order can be found without scanning the book
+    locations: HashMap<String, (OrderType, Price)>,
+    // Stop and stop-limit orders waiting for their stop price, by (stop price, sequence), not part of the visible book
+    buy_stops: BTreeMap<(Price, u64), Order>,
+    sell_stops: BTreeMap<(Price, u64), Order>,
+    // Side and key of every waiting stop order by order id
+    stop_locations: HashMap<String, (OrderType, (Price, u64))>,
+    // Price of the last trade, or the latest price from the market data generator, used to trigger the stop orders
+    last_price: Option<Price>,
+    // Goes up by one for every depth update published for this book
+    depth_sequence: u64,
+    self_trade_prevention: SelfTradePrevention,
+    // Smallest price step, a repriced post-only order is put one tick behind the best price of the opposite side
+    tick_size: Price,
+    // Nothing trades during a call auction, the orders are uncrossed at a single price when it ends
+    phase: SessionPhase,
+    // When the reopening auction of a halted book is due
+    halted_until: Option<u64>,
+    // Price the price band is measured from, the first known price and then the price every auction ended at
+    reference_price: Option<Price>,
+}
+
+impl OrderBook {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            locations: HashMap::new(),
+            buy_stops: BTreeMap::new(),
+            sell_stops: BTreeMap::new(),
+            stop_locations: HashMap::new(),
+            last_price: None,
+            depth_sequence: 0,
+            self_trade_prevention: SelfTradePrevention::default(),
+            tick_size: DEFAULT_TICK_SIZE,
+            phase: SessionPhase::Continuous,
+            halted_until: None,
+            reference_price: None,
+        }
+    }
+
+    // Rebuild the order book from the buy, sell and stop orders stored in Redis
+    pub fn from_orders(symbol: &str, buy_orders: Vec<Order>, sell_orders: Vec<Order>, stop_orders: Vec<Order>) -> Self {
+        // Both sides are saved in priority order, adding them in the same order puts every price level back in time priority
+        // (not by sequence, an iceberg order keeps its sequence but goes to the back of its level every time its peak is refreshed)
+        let mut order_book = Self::new(symbol);
+        for order in buy_orders.into_iter().chain(sell_orders) {
+            order_book.add(order);
+        }
+        for order in stop_orders {
+            order_book.add_stop(order);
+        }
+        order_book
+    }
+
+    // Book as it was saved by saved(), a book saved while halted is halted again
+    pub fn from_saved(saved: SavedOrderBook) -> Self {
+        let mut order_book = Self::from_orders(&saved.stock_symbol, saved.buy_orders, saved.sell_orders, saved.stop_orders);
+        if let Some(halted_until) = saved.halted_until {
+            order_book.halt(halted_until);
+        }
+        order_book
+    }
+
+    // Orders of the book as they are saved, every side in priority order
+    pub fn saved(&self) -> SavedOrderBook {
+        SavedOrderBook {
+            stock_symbol: self.symbol.clone(),
+            buy_orders: self.buy_orders(),
+            sell_orders: self.sell_orders(),
+            stop_orders: self.stop_orders(),
+            halted_until: self.halted_until,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    // Add the order to the back of its price level
+    // An iceberg order without a peak shown yet (visible_quantity 0) gets its first peak
+    pub fn add(&mut self, mut order: Order) {
+        if order.visible_quantity == 0 {
+            refresh_peak(&mut order);
+        }
+        self.locations.insert(order.id.clone(), (order.order_type, order.price));
+        match order.order_type {
+            OrderType::Buy => self.bids.entry(Reverse(order.price)).or_default().push_back(order),
+            OrderType::Sell => self.asks.entry(order.price).or_default().push_back(order),
+        }
+    }
+
+    // Park a stop or stop-limit order until its stop price is reached, the order must have a stop price
+    fn add_stop(&mut self, order: Order) {
+        let key = (order.stop_price.expect("Stop order without stop price"), order.sequence);
+        self.stop_locations.insert(order.id.clone(), (order.order_type, key));
+        match order.order_type {
+            OrderType::Buy => self.buy_stops.insert(key, order),
+            OrderType::Sell => self.sell_stops.insert(key, order),
+        };
+    }
+
+    pub fn set_self_trade_prevention(&mut self, self_trade_prevention: SelfTradePrevention) {
+        self.self_trade_prevention = self_trade_prevention;
+    }
+
+    pub fn set_tick_size(&mut self, tick_size: Price) {
+        self.tick_size = tick_size;
+    }
+
+    // Only changes the phase, call uncross when a call auction ends
+    pub fn set_phase(&mut self, phase: SessionPhase) {
+        self.phase = phase;
+        if phase != SessionPhase::Halted {
+            self.halted_until = None;
+        }
+    }
+
+    pub fn phase(&self) -> SessionPhase {
+        self.phase
+    }
+
+    // Stop trading until the reopening auction, a later halt that is already running is kept
+    pub fn halt(&mut self, until: u64) {
+        self.phase = SessionPhase::Halted;
+        self.halted_until = Some(self.halted_until.map_or(until, |halted_until| halted_until.max(until)));
+    }
+
+    pub fn halted_until(&self) -> Option<u64> {
+        self.halted_until
+    }
+
+    pub fn reference_price(&self) -> Option<Price> {
+        self.reference_price
+    }
+
+    // Measure the price band from the last price from now on, after an auction
+    pub fn reset_reference_price(&mut self) {
+        self.reference_price = self.last_price;
+    }
+
+    // The first known price becomes the reference price, set together with the last price so it doesn't depend on when the band is checked
+    fn record_last_price(&mut self, price: Price) {
+        self.last_price = Some(price);
+        self.reference_price.get_or_insert(price);
+    }
+
+    // How far the last price is from the reference price, 0.1 = 10% above, None if there is no price yet
+    pub fn price_move(&self) -> Option<f64> {
+        let (last_price, reference_price) = (self.last_price?, self.reference_price?);
+        Some((last_price - reference_price).to_f64() / reference_price.to_f64())
+    }
+
+    // Also while halted or closed, nothing matches and the book is uncrossed when it trades continuously again
+    pub fn in_call_auction(&self) -> bool {
+        self.phase != SessionPhase::Continuous
+    }
+
+    pub fn last_price(&self) -> Option<Price> {
+        self.last_price
+    }
+
+    // Set the latest price from outside the order book (the market data generator), call take_triggered_stops afterwards
+    // The trailing stop orders follow the price, returns the ones whose trigger level moved
+    pub fn set_last_price(&mut self, price: Price) -> Vec<Order> {
+        self.record_last_price(price);
+
+        let mut moved: Vec<Order> = Vec::new();
+        for (stops, order_type) in [(&mut self.buy_stops, OrderType::Buy), (&mut self.sell_stops, OrderType::Sell)] {
+            let keys: Vec<(Price, u64)> = stops
+                .iter()
+                .filter(|(_, order)| trailing_stop_moves(order, price))
+                .map(|(key, _)| *key)
+                .collect();
+
+            // The stop price is part of the key, so the order is taken out and put back under its new level
+            for key in keys {
+                let mut order = stops.remove(&key).expect("Key was just found in the stop orders");
+                order.stop_price = trailing_stop_level(&order, price);
+                let new_key = (order.stop_price.expect("Trailing stop order with offset"), order.sequence);
+                self.stop_locations.insert(order.id.clone(), (order_type, new_key));
+                moved.push(order.clone());
+                stops.insert(new_key, order);
+            }
+        }
+        moved
+    }
+
+    // Take out every stop order whose stop price the last price has reached, turned into the order it becomes once triggered
+    // A buy stop triggers when the price rises to or above its stop price, a sell stop when the price falls to or below it
+    // Returned in trigger order: the stop price reached first comes first, then sequence
+    // Nothing triggers during a call auction, the stop orders wait until the book is uncrossed
+    pub fn take_triggered_stops(&mut self) -> Vec<Order> {
+        let Some(last_price) = self.last_price.filter(|_| !self.in_call_auction()) else {
+            return Vec::new();
+        };
+
+        let buy_keys: Vec<(Price, u64)> = self.buy_stops.range(..=(last_price, u64::MAX)).map(|(key, _)| *key).collect();
+        let sell_keys: Vec<(Price, u64)> = self.sell_stops.range((last_price, 0)..).rev().map(|(key, _)| *key).collect();
+
+        let mut triggered: Vec<Order> = Vec::new();
+        for key in buy_keys {
+            triggered.extend(self.buy_stops.remove(&key));
+        }
+        for key in sell_keys {
+            triggered.extend(self.sell_stops.remove(&key));
+        }
+
+        for order in &mut triggered {
+            self.stop_locations.remove(&order.id);
+            order.kind = match order.kind {
+                OrderKind::StopLimit => OrderKind::Limit,
+                _ => OrderKind::Market,
+            };
+        }
+        triggered
+    }
+
+    pub fn best_bid(&self) -> Option<&Order> {
+        self.bids.values().next().and_then(|level| level.front())
+    }
+
+    pub fn best_ask(&self) -> Option<&Order> {
+        self.asks.values().next().and_then(|level| level.front())
+    }
+
+    // Match an incoming order against the opposite side of the book
+    // A limit order rests whatever quantity is left once no more orders cross its price, unless it is IOC or FOK
+    // A market order sweeps the opposite side across price levels, whatever is left once the book runs dry is cancelled
+    // A FOK order only trades if the whole quantity can be filled on arrival, otherwise nothing trades and it is cancelled
+    // An all-or-none order (partial_fill false) only trades if the whole quantity can be filled, otherwise it rests untouched
+    // A stop or stop-limit order is parked until triggered, see take_triggered_stops
+    // A pegged order first takes the price of its reference, a post-only order never trades (it is cancelled or repriced instead)
+    // During a call auction the order rests without trading, only limit orders are expected then
+    pub fn match_order(&mut self, mut order: Order) -> MatchResult {
+        let mut result = MatchResult::default();
+
+        if matches!(order.kind, OrderKind::Stop | OrderKind::StopLimit | OrderKind::TrailingStop) {
+            self.add_stop(order);
+            return result;
+        }
+
+        // Keeps the price it has while there is nothing to peg to, or nothing positive
+        if let Some(pegged_price) = self.pegged_price(&order) {
+            if pegged_price != order.price {
+                order.price = pegged_price;
+                result.repriced = Some(order.clone());
+            }
+        }
+
+        // The book can be crossed during the call, so there is nothing for post-only to check either
+        if self.in_call_auction() {
+            self.add(order);
+            return result;
+        }
+
+        if let Some(post_only) = order.post_only {
+            let opposite_best = match order.order_type {
+                OrderType::Buy => self.best_ask(),
+                OrderType::Sell => self.best_bid(),
+            };
+
+            if let Some(opposite_best) = opposite_best.filter(|opposite_best| crosses(&order, opposite_best)) {
+                match post_only {
+                    PostOnly::Reject => {
+                        result.cancelled = Some(order);
+                        return result;
+                    }
+                    PostOnly::Reprice => {
+                        order.price = match order.order_type {
+                            OrderType::Buy => opposite_best.price - self.tick_size,
+                            OrderType::Sell => opposite_best.price + self.tick_size,
+                        };
+                        result.repriced = Some(order.clone());
+                    }
+                }
+            }
+        }
+
+        let must_fill_completely = order.time_in_force == TimeInForce::FillOrKill || !order.partial_fill;
+        if !must_fill_completely || self.available_quantity(&order) >= order.quantity {
+            match order.order_type {
+                OrderType::Buy => match_against(&mut self.asks, &mut self.locations, self.self_trade_prevention, &mut order, &mut result),
+                OrderType::Sell => match_against(&mut self.bids, &mut self.locations, self.self_trade_prevention, &mut order, &mut result),
+            }
+        }
+
+        if order.quantity > 0 {
+            let can_rest = order.kind == OrderKind::Limit
+                && !matches!(order.time_in_force, TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill);
+
+            if can_rest {
+                self.add(order);
+            } else {
+                result.cancelled = Some(order);
+            }
+        }
+
+        if let Some(trade) = result.trades.last() {
+            self.record_last_price(trade.price);
+        }
+
+        result
+    }
+
+    // Price a pegged order should have right now, None if it is not pegged, there is nothing to peg to
+    // or the reference plus the offset is not a positive price
+    // Rounded to the tick size away from the opposite side, a post-only pegged order stays one tick behind the best price of the opposite side
+    fn pegged_price(&self, order: &Order) -> Option<Price> {
+        let peg = order.peg?;
+        let best_bid = unpegged_best_price(self.bids.values());
+        let best_ask = unpegged_best_price(self.asks.values());
+
+        let reference = match peg.reference {
+            PegReference::BestBid => best_bid?,
+            PegReference::BestOffer => best_ask?,
+            PegReference::Midpoint => Price((best_bid?.0 + best_ask?.0) / 2),
+        };
+        let Price(units) = reference.checked_add(peg.offset)?;
+        // A tick size of 0 (not set properly) leaves the price as it is
+        let tick_size = self.tick_size.0.max(1);
+        let price = match order.order_type {
+            OrderType::Buy => Price(units.div_euclid(tick_size) * tick_size),
+            OrderType::Sell => Price((units + tick_size - 1).div_euclid(tick_size) * tick_size),
+        };
+
+        let price = match (order.post_only, order.order_type) {
+            (None, _) => price,
+            (Some(_), OrderType::Buy) => self.best_ask().map_or(price, |best_ask| price.min(best_ask.price - self.tick_size)),
+            (Some(_), OrderType::Sell) => self.best_bid().map_or(price, |best_bid| price.max(best_bid.price + self.tick_size)),
+        };
+        (price > Price::ZERO).then_some(price)
+    }
+
+    // Give every pegged order whose reference moved its new price, call it whenever the top of the book may have changed
+    // A repriced order is matched again as if it just arrived, so it loses its time priority and can trade
+    // Returns the match result of every repriced order
+    pub fn reprice_pegged(&mut self) -> Vec<MatchResult> {
+        let to_reprice: Vec<String> = self
+            .bids
+            .values()
+            .chain(self.asks.values())
+            .flatten()
+            .filter(|order| self.pegged_price(order).is_some_and(|price| price != order.price))
+            .map(|order| order.id.clone())
+            .collect();
+
+        let mut results: Vec<MatchResult> = Vec::new();
+        for order_id in to_reprice {
+            // Not there anymore if it traded with a pegged order repriced before it
+            if let Some(order) = self.cancel(&order_id) {
+                results.push(self.match_order(order));
+            }
+        }
+        results
+    }
+
+    // Indicative price, matched quantity and imbalance of the call auction if it was uncrossed right now
+    pub fn auction_info(&self, timestamp: u64) -> AuctionInfo {
+        let equilibrium = self.equilibrium();
+        AuctionInfo {
+            stock_symbol: self.symbol.clone(),
+            phase: self.phase,
+            indicative_price: equilibrium.as_ref().map(|equilibrium| equilibrium.price),
+            matched_quantity: equilibrium.as_ref().map_or(0, |equilibrium| equilibrium.matched_quantity),
+            imbalance_quantity: equilibrium.as_ref().map_or(0, |equilibrium| equilibrium.imbalance_quantity),
+            imbalance_side: equilibrium.and_then(|equilibrium| equilibrium.imbalance_side),
+            timestamp,
+        }
+    }
+
+    // Trade every order that crosses at the equilibrium price, all at that single price, at the end of a call auction
+    // Buy and sell orders are filled in price then time priority, the later of two orders of the same account counts as the incoming one
+    // for the self-trade prevention. All-or-none orders don't take part, they wait for continuous trading
+    pub fn uncross(&mut self) -> MatchResult {
+        let mut result = MatchResult::default();
+        let Some(equilibrium) = self.equilibrium() else {
+            return result;
+        };
+        let price = equilibrium.price;
+
+        let buy_ids: Vec<String> = self
+            .bids
+            .values()
+            .flatten()
+            .filter(|order| order.partial_fill && order.price >= price)
+            .map(|order| order.id.clone())
+            .collect();
+        let sell_ids: Vec<String> = self
+            .asks
+            .values()
+            .flatten()
+            .filter(|order| order.partial_fill && order.price <= price)
+            .map(|order| order.id.clone())
+            .collect();
+
+        let (mut buy_index, mut sell_index) = (0, 0);
+        let mut refreshed_ids: Vec<String> = Vec::new();
+        while buy_index < buy_ids.len() && sell_index < sell_ids.len() {
+            let buy_price = self.locations[&buy_ids[buy_index]].1;
+            let sell_price = self.locations[&sell_ids[sell_index]].1;
+            let buy = resting_order_mut(&mut self.bids, &Reverse(buy_price), &buy_ids[buy_index]);
+            let sell = resting_order_mut(&mut self.asks, &sell_price, &sell_ids[sell_index]);
+            let (incoming, resting) = if buy.sequence > sell.sequence { (buy, sell) } else { (sell, buy) };
+
+            if is_self_trade(incoming, resting) {
+                prevent_self_trade(self.self_trade_prevention, incoming, resting, &mut result);
+            } else {
+                // Icebergs take part with their whole quantity, a new peak is shown if the current one is filled
+                let trade_quantity = incoming.quantity.min(resting.quantity);
+                let trade = Trade { price, ..new_trade(incoming, resting, trade_quantity) };
+                for order in [&mut *incoming, &mut *resting] {
+                    order.quantity -= trade_quantity;
+                    order.visible_quantity = order.visible_quantity.saturating_sub(trade_quantity);
+                    if order.display_quantity.is_some() && order.visible_quantity == 0 {
+                        refresh_peak(order);
+                        if !refreshed_ids.contains(&order.id) {
+                            refreshed_ids.push(order.id.clone());
+                        }
+                    }
+                    result.execution_reports.push(record_fill(order, trade_quantity, price, trade.timestamp));
+                }
+                result.trades.push(trade);
+            }
+
+            for order in [incoming, resting] {
+                if order.quantity == 0 {
+                    match order.order_type {
+                        OrderType::Buy => buy_index += 1,
+                        OrderType::Sell => sell_index += 1,
+                    }
+                }
+            }
+        }
+
+        // Take out the filled orders and the ones cancelled by the self-trade prevention
+        for order_id in buy_ids.iter().chain(&sell_ids) {
+            if self.get(order_id).is_some_and(|order| order.quantity == 0) {
+                self.cancel(order_id);
+            }
+        }
+
+        // Like in continuous trading, an iceberg with a refreshed peak loses its time priority
+        for order_id in &refreshed_ids {
+            if let Some(order) = self.cancel(order_id) {
+                self.add(order);
+            }
+        }
+
+        if let Some(trade) = result.trades.last() {
+            self.record_last_price(trade.price);
+        }
+
+        result
+    }
+
+    // Price with the most quantity traded, then the smallest imbalance, then the closest to the last price, then the lowest price
+    // None if nothing would trade
+    fn equilibrium(&self) -> Option<Equilibrium> {
+        let buys: Vec<&Order> = self.bids.values().flatten().filter(|order| order.partial_fill).collect();
+        let sells: Vec<&Order> = self.asks.values().flatten().filter(|order| order.partial_fill).collect();
+
+        let candidates = buys.iter().chain(&sells).map(|order| {
+            let price = order.price;
+            let buy_quantity: u32 = buys.iter().filter(|buy| buy.price >= price).map(|buy| buy.quantity).sum();
+            let sell_quantity: u32 = sells.iter().filter(|sell| sell.price <= price).map(|sell| sell.quantity).sum();
+            Equilibrium {
+                price,
+                matched_quantity: buy_quantity.min(sell_quantity),
+                imbalance_quantity: buy_quantity.abs_diff(sell_quantity),
+                imbalance_side: match buy_quantity.cmp(&sell_quantity) {
+                    std::cmp::Ordering::Greater => Some(OrderType::Buy),
+                    std::cmp::Ordering::Less => Some(OrderType::Sell),
+                    std::cmp::Ordering::Equal => None,
+                },
+            }
+        });
+
+        let reference = self.last_price;
+        candidates
+            .min_by_key(|candidate| {
+                let distance = reference.map_or(0, |reference| (candidate.price - reference).0.abs());
+                (Reverse(candidate.matched_quantity), candidate.imbalance_quantity, distance, candidate.price)
+            })
+            .filter(|equilibrium| equilibrium.matched_quantity > 0)
+    }
+
+    // Quantity the order would get filled if it was matched right now, stops counting once it reaches the order quantity
+    // Walks the opposite side exactly like match_against does, without changing anything
+    fn available_quantity(&self, order: &Order) -> u32 {
+        let opposite: Box<dyn Iterator<Item = &Order>> = match order.order_type {
+            OrderType::Buy => Box::new(self.asks.values().flatten()),
+            OrderType::Sell => Box::new(self.bids.values().flatten()),
+        };
+
+        let mut remaining = order.quantity;
+        for resting in opposite.take_while(|resting| crosses(order, resting)) {
+            if remaining == 0 {
+                break;
+            }
+            // Only cancelling the resting order lets the incoming order go on matching untouched
+            if is_self_trade(order, resting) {
+                if self.self_trade_prevention == SelfTradePrevention::CancelOldest {
+                    continue;
+                }
+                break;
+            }
+            if can_fill(resting, remaining) {
+                remaining -= remaining.min(resting.quantity);
+            }
+        }
+        order.quantity - remaining
+    }
+
+    // Remove every order whose expire_time is at or before now, returns the removed orders
+    pub fn remove_expired(&mut self, now: u64) -> Vec<Order> {
+        let mut expired: Vec<Order> = Vec::new();
+        remove_expired_from(&mut self.bids, now, &mut expired);
+        remove_expired_from(&mut self.asks, now, &mut expired);
+        for order in &expired {
+            self.locations.remove(&order.id);
+        }
+
+        let is_expired = |order: &Order| order.expire_time.is_some_and(|expire_time| expire_time <= now);
+        for stops in [&mut self.buy_stops, &mut self.sell_stops] {
+            let expired_stops: Vec<Order> = stops.values().filter(|order| is_expired(order)).cloned().collect();
+            stops.retain(|_, order| !is_expired(order));
+            for order in expired_stops {
+                self.stop_locations.remove(&order.id);
+                expired.push(order);
+            }
+        }
+        expired
+    }
+
+    // Resting order with the given id (stop orders waiting for their trigger are not resting)
+    pub fn get(&self, order_id: &str) -> Option<&Order> {
+        let (order_type, price) = self.locations.get(order_id)?;
+        let level = match order_type {
+            OrderType::Buy => self.bids.get(&Reverse(*price))?,
+            OrderType::Sell => self.asks.get(price)?,
+        };
+        level.iter().find(|order| order.id == order_id)
+    }
+
+    // Remove the resting or waiting stop order with the given id from the book
+    // Returns None if it is not in the book (unknown, filled, cancelled or expired)
+    pub fn cancel(&mut self, order_id: &str) -> Option<Order> {
+        if let Some((order_type, key)) = self.stop_locations.remove(order_id) {
+            return match order_type {
+                OrderType::Buy => self.buy_stops.remove(&key),
+                OrderType::Sell => self.sell_stops.remove(&key),
+            };
+        }
+
+        let (order_type, price) = self.locations.remove(order_id)?;
+        match order_type {
+            OrderType::Buy => remove_from_level(&mut self.bids, &Reverse(price), order_id),
+            OrderType::Sell => remove_from_level(&mut self.asks, &price, order_id),
+        }
+    }
+
+    // Lower the quantity of a resting order in place so it keeps its time priority, returns false if the order is not resting
+    pub fn reduce_quantity(&mut self, order_id: &str, quantity: u32) -> bool {
+        let Some((order_type, price)) = self.locations.get(order_id).copied() else {
+            return false;
+        };
+        let level = match order_type {
+            OrderType::Buy => self.bids.get_mut(&Reverse(price)),
+            OrderType::Sell => self.asks.get_mut(&price),
+        };
+
+        match level.and_then(|orders| orders.iter_mut().find(|order| order.id == order_id)) {
+            Some(order) if quantity > 0 && quantity <= order.quantity => {
+                order.quantity = quantity;
+                order.visible_quantity = order.visible_quantity.min(quantity);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Highest sequence number in the book, 0 if the book is empty
+    pub fn max_sequence(&self) -> u64 {
+        let resting = self.bids.values().chain(self.asks.values()).flatten();
+        let stops = self.buy_stops.values().chain(self.sell_stops.values());
+        resting.chain(stops).map(|order| order.sequence).max().unwrap_or(0)
+    }
+
+    // Aggregated top levels of both sides
+    pub fn depth_snapshot(&self, levels: usize, timestamp: u64) -> DepthSnapshot {
+        DepthSnapshot {
+            stock_symbol: self.symbol.clone(),
+            bids: depth_levels(self.bids.values(), levels),
+            asks: depth_levels(self.asks.values(), levels),
+            sequence: self.depth_sequence,
+            timestamp,
+        }
+    }
+
+    // Compare the top levels with a snapshot of the same number of levels taken before the book changed
+    // Returns None if none of the top levels changed
+    pub fn depth_update(&mut self, before: &DepthSnapshot, levels: usize, timestamp: u64) -> Option<DepthUpdate> {
+        let after = self.depth_snapshot(levels, timestamp);
+
+        let mut changes: Vec<DepthChange> = Vec::new();
+        depth_changes(OrderType::Buy, &before.bids, &after.bids, &mut changes);
+        depth_changes(OrderType::Sell, &before.asks, &after.asks, &mut changes);
+
+        if changes.is_empty() {
+            return None;
+        }
+
+        self.depth_sequence += 1;
+        Some(DepthUpdate {
+            stock_symbol: self.symbol.clone(),
+            changes,
+            sequence: self.depth_sequence,
+            timestamp,
+        })
+    }
+
+    // Buy orders in priority order (highest price first, then sequence)
+    pub fn buy_orders(&self) -> Vec<Order> {
+        self.bids.values().flatten().cloned().collect()
+    }
+
+    // Sell orders in priority order (lowest price first, then sequence)
+    pub fn sell_orders(&self) -> Vec<Order> {
+        self.asks.values().flatten().cloned().collect()
+    }
+
+    // Stop orders waiting for their trigger
+    pub fn stop_orders(&self) -> Vec<Order> {
+        self.buy_stops.values().chain(self.sell_stops.values()).cloned().collect()
+    }
+}
+
+// Walk the opposite side from the best price level, filling resting orders in time priority until the incoming order is filled or the price no longer crosses
+// Resting all-or-none orders that the incoming order cannot fill completely are skipped and keep their place
+// A resting iceberg order only trades its shown peak, once the peak is filled it shows a new one from the hidden quantity at the back of its level
+// A resting order of the same account is handled by the self-trade prevention mode instead of trading
+fn match_against<K: Ord + Clone>(
+    side: &mut BTreeMap<K, VecDeque<Order>>,
+    locations: &mut HashMap<String, (OrderType, Price)>,
+    self_trade_prevention: SelfTradePrevention,
+    order: &mut Order,
+    result: &mut MatchResult,
+) {
+    let mut emptied_levels: Vec<K> = Vec::new();
+
+    for (price_level, orders) in side.iter_mut() {
+        // Every order in a level has the same price, so checking the first one is enough
+        if order.quantity == 0 || !orders.front().is_some_and(|resting| crosses(order, resting)) {
+            break;
+        }
+
+        let mut index = 0;
+        while index < orders.len() && order.quantity > 0 {
+            let resting = &mut orders[index];
+            if is_self_trade(order, resting) {
+                prevent_self_trade(self_trade_prevention, order, resting, result);
+                if resting.quantity == 0 {
+                    locations.remove(&resting.id);
+                    orders.remove(index);
+                } else {
+                    index += 1;
+                }
+                continue;
+            }
+            if !can_fill(resting, order.quantity) {
+                index += 1;
+                continue;
+            }
+
+            // Take the minimum quantity between the incoming and shown resting quantity
+            let trade_quantity = order.quantity.min(shown_quantity(resting));
+            let trade = new_trade(order, resting, trade_quantity);
+
+            order.quantity -= trade_quantity;
+            resting.quantity -= trade_quantity;
+            resting.visible_quantity = resting.visible_quantity.saturating_sub(trade_quantity);
+            result.execution_reports.push(record_fill(order, trade_quantity, trade.price, trade.timestamp));
+            result.execution_reports.push(record_fill(resting, trade_quantity, trade.price, trade.timestamp));
+            result.trades.push(trade);
+            if resting.quantity == 0 {
+                locations.remove(&resting.id);
+                orders.remove(index);
+            } else if resting.display_quantity.is_some() && resting.visible_quantity == 0 {
+                // Peak filled, refresh it from the hidden quantity and lose the time priority
+                let mut refreshed = orders.remove(index).expect("Order was just filled at this index");
+                refresh_peak(&mut refreshed);
+                orders.push_back(refreshed);
+            } else {
+                index += 1;
+            }
+        }
+
+        if orders.is_empty() {
+            emptied_levels.push(price_level.clone());
+        }
+    }
+
+    for price_level in emptied_levels {
+        side.remove(&price_level);
+    }
+}
+
+fn is_self_trade(order: &Order, resting: &Order) -> bool {
+    order.account_id.is_some() && order.account_id == resting.account_id
+}
+
+// Cancel or decrement the incoming and resting order of the same account, a cancelled order is left with quantity 0
+fn prevent_self_trade(self_trade_prevention: SelfTradePrevention, order: &mut Order, resting: &mut Order, result: &mut MatchResult) {
+    let timestamp = std::cmp::max(order.timestamp, resting.timestamp);
+
+    if self_trade_prevention == SelfTradePrevention::Decrement {
+        let decrement = order.quantity.min(resting.quantity);
+        for decremented in [&mut *order, &mut *resting] {
+            decremented.quantity -= decrement;
+            decremented.visible_quantity = decremented.visible_quantity.min(decremented.quantity);
+            let exec_type = if decremented.quantity == 0 { ExecType::Cancelled } else { ExecType::Restated };
+            result.execution_reports.push(new_execution_report_with_reason(decremented, exec_type, "Decremented by self-trade prevention", timestamp));
+        }
+        return;
+    }
+
+    let (cancel_incoming, cancel_resting) = match self_trade_prevention {
+        SelfTradePrevention::CancelNewest => (true, false),
+        SelfTradePrevention::CancelOldest => (false, true),
+        _ => (true, true),
+    };
+    for (cancelled, cancel) in [(order, cancel_incoming), (resting, cancel_resting)] {
+        if cancel {
+            result.execution_reports.push(new_execution_report_with_reason(cancelled, ExecType::Cancelled, "Cancelled by self-trade prevention", timestamp));
+            cancelled.quantity = 0;
+        }
+    }
+}
+
+// Whether the incoming order is allowed to trade with the resting order at the resting order's price
+fn crosses(order: &Order, resting: &Order) -> bool {
+    match (order.kind, &order.order_type) {
+        (OrderKind::Market | OrderKind::Stop | OrderKind::TrailingStop, _) => true,
+        // A buy order can trade with any sell order priced at or below it
+        (OrderKind::Limit | OrderKind::StopLimit, OrderType::Buy) => resting.price <= order.price,
+        // A sell order can trade with any buy order priced at or above it
+        (OrderKind::Limit | OrderKind::StopLimit, OrderType::Sell) => resting.price >= order.price,
+    }
+}
+
+// Price of the best order that is not pegged, pegged orders can't be the reference of other pegged orders
+fn unpegged_best_price<'a>(price_levels: impl Iterator<Item = &'a VecDeque<Order>>) -> Option<Price> {
+    price_levels.flatten().find(|order| order.peg.is_none()).map(|order| order.price)
+}
+
+// Trigger level of a trailing stop order for a price, None if the order has no trailing offset
+// or the level is not a positive price (an offset larger than the price, or one that overflows)
+pub fn trailing_stop_level(order: &Order, price: Price) -> Option<Price> {
+    let offset = match order.trailing_offset? {
+        TrailingOffset::Amount(amount) => amount,
+        TrailingOffset::Percent(percent) => Price::from_f64(price.to_f64() * percent / 100.0),
+    };
+
+    let level = match order.order_type {
+        OrderType::Buy => price.checked_add(offset)?,
+        OrderType::Sell => price.checked_sub(offset)?,
+    };
+    (level > Price::ZERO).then_some(level)
+}
+
+// Whether the price moves the trigger level of a trailing stop order, it only ever moves towards the market
+// (down for a buy trailing stop, up for a sell trailing stop)
+fn trailing_stop_moves(order: &Order, price: Price) -> bool {
+    if order.kind != OrderKind::TrailingStop {
+        return false;
+    }
+
+    match (order.order_type, order.stop_price, trailing_stop_level(order, price)) {
+        (OrderType::Buy, Some(stop_price), Some(level)) => level < stop_price,
+        (OrderType::Sell, Some(stop_price), Some(level)) => level > stop_price,
+        _ => false,
+    }
+}
+
+// Show the next peak of an iceberg order, does nothing for other orders
+fn refresh_peak(order: &mut Order) {
+    if let Some(display_quantity) = order.display_quantity {
+        order.visible_quantity = display_quantity.min(order.quantity);
+    }
+}
+
+// Whether the resting order can trade with an incoming quantity, an all-or-none resting order needs to be filled completely
+fn can_fill(resting: &Order, incoming_quantity: u32) -> bool {
+    resting.partial_fill || resting.quantity <= incoming_quantity
+}
+
+fn depth_levels<'a>(price_levels: impl Iterator<Item = &'a VecDeque<Order>>, levels: usize) -> Vec<DepthLevel> {
+    price_levels
+        .take(levels)
+        .map(|orders| DepthLevel {
+            price: orders[0].price,
+            // Hidden quantity of iceberg orders is left out
+            quantity: orders.iter().map(shown_quantity).sum(),
+            order_count: orders.len() as u32,
+        })
+        .collect()
+}
+
+// Levels that are new or changed in after, and levels of before that are gone (sent with quantity 0)
+fn depth_changes(side: OrderType, before: &[DepthLevel], after: &[DepthLevel], changes: &mut Vec<DepthChange>) {
+    for level in after {
+        if !before.contains(level) {
+            changes.push(DepthChange { side, price: level.price, quantity: level.quantity, order_count: level.order_count });
+        }
+    }
+    for level in before {
+        if !after.iter().any(|after_level| after_level.price == level.price) {
+            changes.push(DepthChange { side, price: level.price, quantity: 0, order_count: 0 });
+        }
+    }
+}
+
+// Resting order that is known to be at this price level
+fn resting_order_mut<'a, K: Ord>(side: &'a mut BTreeMap<K, VecDeque<Order>>, price_level: &K, order_id: &str) -> &'a mut Order {
+    side.get_mut(price_level)
+        .and_then(|orders| orders.iter_mut().find(|order| order.id == order_id))
+        .expect("Order was just found in the order book")
+}
+
+fn remove_from_level<K: Ord>(side: &mut BTreeMap<K, VecDeque<Order>>, price_level: &K, order_id: &str) -> Option<Order> {
+    let orders = side.get_mut(price_level)?;
+    let index = orders.iter().position(|order| order.id == order_id)?;
+    let order = orders.remove(index);
+
+    if orders.is_empty() {
+        side.remove(price_level);
+    }
+    order
+}
+
+fn remove_expired_from<K: Ord>(side: &mut BTreeMap<K, VecDeque<Order>>, now: u64, expired: &mut Vec<Order>) {
+    side.retain(|_, orders| {
+        orders.retain(|order| match order.expire_time {
+            Some(expire_time) if expire_time <= now => {
+                expired.push(order.clone());
+                false
+            }
+            _ => true,
+        });
+        !orders.is_empty()
+    });
+}
+
+fn new_trade(incoming: &Order, resting: &Order, quantity: u32) -> Trade {
+    let (buy_order, sell_order) = match incoming.order_type {
+        OrderType::Buy => (incoming, resting),
+        OrderType::Sell => (resting, incoming),
+    };
+
+    Trade {
+        buy_order_id: buy_order.id.clone(),
+        sell_order_id: sell_order.id.clone(),
+        stock_symbol: incoming.stock_symbol.clone(),
+        quantity,
+        price: resting.price,   // Take the resting order price, the incoming order is the one crossing the spread
+        timestamp: std::cmp::max(buy_order.timestamp, sell_order.timestamp),
+        buy_sequence: buy_order.sequence,
+        sell_sequence: sell_order.sequence,
+    }
+}
@@ -1,324 +1,253 @@
-use redis::{aio, AsyncCommands, RedisResult};   // RedisResult: Result type for Redis commands
-use common::models::{Order, OrderType, Trade};
-use serde_json::{from_str, to_string};  // Deserialize JSON string to struct; Serialize struct to JSON string
-// use std::sync::Arc;
-// use tokio::sync::Mutex;  // Mutex: Mutual Exclusion, used to synchronize access to shared data
-
-pub struct OrderBookManager {
-    // ARC: Atomic Reference Counting, used to share ownership between threads
-    // Mutex: Mutual Exclusion, used to synchronize access to shared data
-    // So with arc and mutex, we can ensure that only one thread can access the Redis connection at a time
-    redis_conn: aio::MultiplexedConnection,
+use common::models::{CancelRequest, DepthSnapshotRequest, Order, RejectReason, ReplaceRequest, Stock, TimeInForce};
+use common::price::Price;
+use common::storage::{Storage, StorageResult};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;  // Mutex: Mutual Exclusion, used to synchronize access to shared data
+
+use crate::execution_report::{new_ack, new_rejected_execution_report};
+use crate::journal::{Journal, JournalEvent};
+use crate::matching_engine::{EngineSettings, MatchingEngine, OrderEvents, SECONDS_PER_DAY};
+use crate::order_book::OrderBook;
+use crate::validation::{validate_order, validate_replace_price};
+
+pub struct OrderBookManager<S: Storage> {
+    // Prices, reference data and a copy of each book so they survive restarts and the market data generator can read them
+    storage: S,
+    // In-memory order book of every stock symbol, this is where the matching happens
+    // Mutex because the manager is shared between tasks
+    engine: Mutex<MatchingEngine>,
+    // Every request that changed the order books and the trades it made, written before the books are saved in the storage
+    // Only locked while holding the engine, so the entries are in the same order as the matching
+    journal: Mutex<Journal>,
+    // Order books whose last save failed, saved again with the next save or on the next expiry check
+    unsaved: Mutex<BTreeSet<String>>,
+    // Latest price of every stock that changed nothing yet, journaled ahead of the next entry instead of on every tick
+    // Only locked while holding the engine, like the journal
+    unjournaled_prices: Mutex<BTreeMap<String, Stock>>,
+    // Session close as seconds after midnight UTC, DAY orders expire at the next session close after they are accepted
+    session_close: u64,
 }
 
-impl OrderBookManager {
-    pub async fn new(redis_url: &str) -> Self {
-        println!("OrderBookManager: Connecting to Redis: {}", redis_url);
-        let client = redis::Client::open(redis_url).unwrap();
-        let redis_conn = client
-            .get_multiplexed_async_connection()
+impl<S: Storage> OrderBookManager<S> {
+    // The settings come without reference data, it is loaded from the storage
+    pub async fn new(storage: S, journal_path: &str, session_close: u64, mut settings: EngineSettings) -> Self {
+        // Taken before the order books, a restore is always followed by loading what it wrote
+        let restored = storage.take_restored().await.expect("OrderBookManager: Failed to check for a restore");
+
+        // Restore the order books from the storage
+        let order_books = storage
+            .load_order_books()
+            .await
+            .expect("OrderBookManager: Failed to load order books")
+            .into_iter()
+            .map(|saved| (saved.stock_symbol.clone(), OrderBook::from_saved(saved)))
+            .collect::<BTreeMap<String, OrderBook>>();
+        settings.reference_data = storage
+            .reference_data()
+            .await
+            .expect("OrderBookManager: Failed to load reference data");
+
+        // Continue after both the saved sequence and the highest sequence still resting in a book
+        let saved_sequence = storage
+            .sequence()
+            .await
+            .expect("OrderBookManager: Failed to load sequence number");
+        let max_sequence = order_books.values().map(|order_book| order_book.max_sequence()).max().unwrap_or(0);
+        let next_sequence = saved_sequence.unwrap_or(0).max(max_sequence) + 1;
+
+        // The halted books are halted in the books themselves, a market-wide halt also halts the books created during it
+        let market_halted_until = storage
+            .market_halted_until()
             .await
-            .expect("OrderBookManager: Failed to connect to Redis");
+            .expect("OrderBookManager: Failed to load market-wide halt");
+
+        println!("OrderBookManager: Loaded {} order books, next sequence {}", order_books.len(), next_sequence);
+
+        let now = now();
+        let engine = MatchingEngine::new(order_books, next_sequence, market_halted_until, settings, now);
 
-        println!("OrderBookManager: Connected to Redis");
+        // The journal starts from what was loaded, so replaying it from here gives the same order books
+        let mut journal = Journal::open(journal_path).expect("OrderBookManager: Failed to open journal");
+        let started = JournalEvent::Started {
+            settings: engine.settings().clone(),
+            next_sequence: engine.next_sequence(),
+            market_halted_until: engine.market_halted_until(),
+            order_books: engine.order_books().values().map(OrderBook::saved).collect(),
+            restored,
+        };
+        journal.append(now, vec![started]).expect("OrderBookManager: Failed to write journal");
 
         Self {
-            redis_conn,
+            storage,
+            engine: Mutex::new(engine),
+            journal: Mutex::new(journal),
+            unsaved: Mutex::new(BTreeSet::new()),
+            unjournaled_prices: Mutex::new(BTreeMap::new()),
+            session_close,
+        }
+    }
+
+    // Validate and accept the incoming order, then match it against its order book (see MatchingEngine::accept_order)
+    // Returns every trade produced by this order in the order they were made, with the execution reports of every order involved
+    pub async fn process_order(&self, mut order: Order) -> StorageResult<OrderEvents> {
+        // The order is accepted while holding the lock, so its sequence number follows the matching order
+        let mut engine = self.engine.lock().await;
+        let mut events = OrderEvents::default();
+
+        order.filled_quantity = 0;
+        order.average_price = Price::ZERO;
+        order.visible_quantity = 0;
+
+        // Only DAY and GTD orders expire, an expire time sent with any other order is dropped
+        let now = now();
+        order.expire_time = match order.time_in_force {
+            TimeInForce::Day => Some(next_session_close(now, self.session_close)),
+            TimeInForce::GoodTillDate => order.expire_time,
+            _ => None,
+        };
+
+        // Anything invalid is rejected before it gets near the order book, the sender gets the reason in the rejected execution report
+        let stock_price = self.storage.price(&order.stock_symbol).await?;
+        let checked = validate_order(&order, stock_price, engine.reference_data(&order.stock_symbol), now)
+            .and_then(|_| engine.prepare_order(&mut order, now));
+        if let Err(reject_reason) = checked {
+            events.execution_reports.push(new_rejected_execution_report(&order, reject_reason, now));
+            return Ok(events);
+        }
+
+        // Taken last, so the id of a rejected order can be sent again
+        let new_order_id = self.storage.add_order_id(&order.id).await?;
+        if !new_order_id {
+            events.execution_reports.push(new_rejected_execution_report(&order, RejectReason::DuplicateOrderId, now));
+            return Ok(events);
+        }
+
+        let events = engine.accept_order(order.clone(), now);
+        self.record(&engine, JournalEvent::NewOrder(order), &events, now).await;
+        Ok(events)
+    }
+
+    // Remove a resting order from its order book, answered with an ack or reject
+    pub async fn cancel_order(&self, request: CancelRequest) -> OrderEvents {
+        let mut engine = self.engine.lock().await;
+
+        let now = now();
+        let events = engine.cancel_order(request.clone(), now);
+        self.record(&engine, JournalEvent::Cancel(request), &events, now).await;
+        events
+    }
+
+    // Cancel/replace a resting order, answered with an ack or reject (see MatchingEngine::replace_order)
+    // The new price is checked against the stock price first, like the price of a new order, a request rejected for it is not journaled
+    pub async fn replace_order(&self, request: ReplaceRequest) -> StorageResult<OrderEvents> {
+        let mut engine = self.engine.lock().await;
+
+        let now = now();
+        let stock_price = self.storage.price(&request.stock_symbol).await?;
+        if let Err(reject_reason) = validate_replace_price(&request, stock_price) {
+            let ack = new_ack(&request.id, &request.order_id, Some(reject_reason), now);
+            return Ok(OrderEvents { ack: Some(ack), ..Default::default() });
         }
+
+        let events = engine.replace_order(request.clone(), now);
+        self.record(&engine, JournalEvent::Replace(request), &events, now).await;
+        Ok(events)
     }
 
-    // return ok or not status
-    pub async fn add_to_orderbook(&self, order: Order) -> RedisResult<()> {
-        let mut conn = self.redis_conn.clone();
-
-        // Key for the order book
-        let order_book_key = format!("order_book:{}", order.stock_symbol);
-
-        // If the order is a buy order, add it to the buy orders in Redis
-        match order.order_type {
-            OrderType::Buy => {
-                // Get the buy orders from Redis, reason is just to sort it later
-                let buy_orders_string: Option<String> = conn.hget(&order_book_key, "buy_orders").await?;
-                let mut buy_orders: Vec<Order> = match buy_orders_string {
-                    Some(buy_orders) => from_str(&buy_orders).expect("Failed to deserialize buy orders"),
-                    None => Vec::new(),
-                };
-
-                buy_orders.push(order.clone());
-
-                // Sort the buy orders in descending order of price
-                buy_orders.sort_by(|a, b| b.price.partial_cmp(&a.price).unwrap());
-
-                // Serialize the buy orders and update the buy orders in Redis
-                let to_redis_buy_orders_string = to_string(&buy_orders).expect("Failed to serialize buy orders");
-                conn.hset(&order_book_key, "buy_orders", to_redis_buy_orders_string).await?;
-
-                Ok(())
-            }
-            // If the order is a sell order, add it to the sell orders in Redis
-            OrderType::Sell => {
-                let sell_orders_string: Option<String> = conn.hget(&order_book_key, "sell_orders").await?;
-                let mut sell_orders: Vec<Order> = match sell_orders_string {
-                    Some(sell_orders) => from_str(&sell_orders).expect("Failed to deserialize sell orders"),
-                    None => Vec::new(),
-                };
-
-                sell_orders.push(order.clone());
-
-                // Sort the sell orders in ascending order of price
-                sell_orders.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap());
-
-                // Serialize the sell orders and update the sell orders in Redis
-                let to_redis_sell_orders_string = to_string(&sell_orders).expect("Failed to serialize sell orders");
-                conn.hset(&order_book_key, "sell_orders", to_redis_sell_orders_string).await?;
-
-                Ok(())
-            }
+    // Latest price of a stock from the market data generator, can move trailing stops and trigger stop orders
+    // Journaled right away only when it moved or triggered something, otherwise it is kept until the next entry is journaled
+    // The last price is part of the state of the order book, a replay needs it before the next request that may depend on it
+    pub async fn update_last_price(&self, stock: &Stock) -> OrderEvents {
+        let mut engine = self.engine.lock().await;
+
+        let now = now();
+        let events = engine.update_last_price(stock, now);
+        if !engine.order_books().contains_key(&stock.symbol) {
+            return events;
+        }
+
+        if events.changed_order_books.is_empty() {
+            self.unjournaled_prices.lock().await.insert(stock.symbol.clone(), stock.clone());
+        } else {
+            self.record(&engine, JournalEvent::LastPrice(stock.clone()), &events, now).await;
         }
+        events
     }
 
-    pub async fn process_order(&self) -> RedisResult<Option<Trade>> {
-        // Get the different order books from Redis
-        let mut conn = self.redis_conn.clone();
-        let order_books: Vec<String> = conn.keys("order_book:*").await?;
-
-        // Iterate over the order books
-        for order_book_key in order_books {
-            // Get the buy and sell orders from Redis
-            let (buy_orders_string, sell_orders_string): (Option<String>, Option<String>) = conn
-                .hget(&order_book_key, &["buy_orders", "sell_orders"])
-                .await?;
-
-            // Deserialize the buy and sell orders into Vec<Order>
-            let mut buy_orders: Vec<Order> = match buy_orders_string {
-                Some(buy_orders) => from_str(&buy_orders).expect("Failed to deserialize buy orders"),
-                None => Vec::new(),
-            };
-
-            let mut sell_orders: Vec<Order> = match sell_orders_string {
-                Some(sell_orders) => from_str(&sell_orders).expect("Failed to deserialize sell orders"),
-                None => Vec::new(),
-            };
-
-            // Check the first buy order (largest) and the first sell order (smallest) to see if a trade can be made
-            while let (Some(buy_order), Some(sell_order)) = (buy_orders.first(), sell_orders.first()) {
-                if buy_order.price >= sell_order.price {
-                    // Take the minimum quantity between the buy and sell orders
-                    let trade_quantity = buy_order.quantity.min(sell_order.quantity);
-
-                    // Create a trade
-                    let trade = Trade {
-                        buy_order_id: buy_order.id.clone(),
-                        sell_order_id: sell_order.id.clone(),
-                        stock_symbol: buy_order.stock_symbol.clone(),
-                        quantity: trade_quantity,
-                        price: sell_order.price,   // Take sell order because a trade should take the lower price
-                        timestamp: std::cmp::max(buy_order.timestamp, sell_order.timestamp),
-                    };
-
-                    // Update the quantity of either the buy or sell order
-                    if buy_order.quantity > trade_quantity {
-                        buy_orders[0].quantity -= trade_quantity;
-                        sell_orders.remove(0);
-                    } else if sell_order.quantity > trade_quantity {
-                        sell_orders[0].quantity -= trade_quantity;
-                        buy_orders.remove(0);
-                    } else {
-                        buy_orders.remove(0);
-                        sell_orders.remove(0);
-                    }
-
-                    // Update the buy and sell orders in Redis
-                    let to_redis_buy_orders_string = to_string(&buy_orders).expect("Failed to serialize buy orders");
-                    conn.hset(&order_book_key, "buy_orders", to_redis_buy_orders_string).await?;
-
-                    let to_redis_sell_orders_string = to_string(&sell_orders).expect("Failed to serialize sell orders");
-                    conn.hset(&order_book_key, "sell_orders", to_redis_sell_orders_string).await?;
-                    
-                    return Ok(Some(trade));
-                } else {
-                    // If the buy order price is less than the sell order price, then no trade can be made, meaning the highest buy order price is less than the lowest sell order price
-                    break;
-                }
-            }
+    // Start or end the call auctions and halts, called every second (see MatchingEngine::update_session_phase)
+    pub async fn update_session_phase(&self) -> OrderEvents {
+        let mut engine = self.engine.lock().await;
+
+        let now = now();
+        let events = engine.update_session_phase(now);
+        if !events.status.is_empty() {
+            self.record(&engine, JournalEvent::SessionPhase, &events, now).await;
         }
+        events
+    }
 
-        Ok(None)
+    // Remove every DAY and GTD order that has expired from all order books, called every second
+    // Order books that failed to save are saved again here, so they don't wait for their next change
+    pub async fn expire_orders(&self) -> OrderEvents {
+        let mut engine = self.engine.lock().await;
+
+        let now = now();
+        let events = engine.expire_orders(now);
+        if !events.changed_order_books.is_empty() {
+            self.record(&engine, JournalEvent::Expire, &events, now).await;
+        } else {
+            self.save_order_books(&engine, &[]).await;
+        }
+        events
     }
 
-    // pub async fn process_order(&self, order: Order) -> RedisResult<Option<Trade>> {
-    //     let order_book_key = format!("order_book:{}", order.stock_symbol);
-    //     let mut conn = self.redis_conn.clone();
-
-    //     // Get the buy and sell orders from Redis
-    //     let (buy_orders_string, sell_orders_string): (Option<String>, Option<String>) = conn
-    //         .hget(&order_book_key, &["buy_orders", "sell_orders"])
-    //         .await?;
-
-    //     // Deserialize the buy and sell orders into Vec<Order>
-    //     let buy_orders: Vec<Order> = match buy_orders_string {
-    //         Some(buy_orders) => from_str(&buy_orders).expect("Failed to deserialize buy orders"),
-    //         None => Vec::new(),
-    //     };
-
-    //     let sell_orders: Vec<Order> = match sell_orders_string {
-    //         Some(sell_orders) => from_str(&sell_orders).expect("Failed to deserialize sell orders"),
-    //         None => Vec::new(),
-    //     };
-
-    //     // Get the order type to check if it is a buy or sell order
-    //     let order_type: OrderType = order.order_type.clone();
-
-    //     // Trade is an Option<Trade> because it may or may not be generated
-    //     let trade: Option<Trade> = match order_type {
-    //         OrderType::Buy => {
-    //             handle_buy_order(&mut conn, &order_book_key, order.clone(), buy_orders, sell_orders).await?
-    //         }
-    //         OrderType::Sell => {
-    //             handle_sell_order(&mut conn, &order_book_key, order.clone(), sell_orders, buy_orders).await?
-    //         }
-    //     };
-    //     Ok(trade)
-    // }
+    // Answer a depth snapshot request with the top levels of the order book, empty if the stock has no order book yet
+    pub async fn depth_snapshot(&self, request: DepthSnapshotRequest) -> OrderEvents {
+        let engine = self.engine.lock().await;
+        engine.depth_snapshot(&request, now())
+    }
+
+    // Journal the request with the trades it made, then save the order books it changed in the storage
+    // The last prices not journaled yet go first, they came before the request
+    // A journal that can't be written stops the order book manager, nothing may reach the storage or the topics without being in it
+    // A failed save doesn't stop the events, the matching already happened and is in the journal, so they are published anyway
+    async fn record(&self, engine: &MatchingEngine, event: JournalEvent, events: &OrderEvents, now: u64) {
+        let mut unjournaled_prices = self.unjournaled_prices.lock().await;
+        let mut journal_events: Vec<JournalEvent> = std::mem::take(&mut *unjournaled_prices).into_values().map(JournalEvent::LastPrice).collect();
+        journal_events.push(event);
+        journal_events.extend(events.trades.iter().cloned().map(JournalEvent::Trade));
+        self.journal.lock().await.append(now, journal_events).expect("OrderBookManager: Failed to write journal");
+
+        self.save_order_books(engine, &events.changed_order_books).await;
+    }
+
+    // Save the changed order books together with every one that failed to save before
+    async fn save_order_books(&self, engine: &MatchingEngine, changed_order_books: &[String]) {
+        let mut unsaved = self.unsaved.lock().await;
+        unsaved.extend(changed_order_books.iter().cloned());
+        if unsaved.is_empty() {
+            return;
+        }
+
+        let order_books = unsaved.iter().map(|stock_symbol| engine.order_books()[stock_symbol].saved()).collect();
+        match self.storage.save_order_books(order_books, engine.last_sequence(), engine.market_halted_until()).await {
+            Ok(()) => unsaved.clear(),
+            Err(e) => eprintln!("OrderBookManager: Failed to save {} order books, trying again: {}", unsaved.len(), e),
+        }
+    }
 }
 
-// async fn handle_buy_order(
-//     conn: &mut aio::MultiplexedConnection,
-//     order_book_key: &str,
-//     mut order: Order,
-//     mut buy_orders: Vec<Order>,
-//     mut sell_orders: Vec<Order>,
-// ) -> RedisResult<Option<Trade>> {
-//     if let Some((index, matching_order)) = sell_orders
-//         .iter()  // Iterate over the sell orders
-//         .enumerate()  // Enumerate the sell orders
-//         .find(|(_, sell_order)| {
-//             sell_order.price <= order.price &&  // Find the first sell order with a price less than or equal to the buy order price
-//             (order.partial_fill || sell_order.quantity >= order.quantity)  // Additional filter: check quantity only if partial_fill is false; must make sure that the sell order quantity is greater than or equal to the buy order quantity
-//         })
-//         .map(|(index, sell_order)| (index, sell_order.clone())) // Clone the matching order to avoid borrowing issues
-//     {
-//         // Update the sell orders in Redis
-//         let mut trade_quantity = order.quantity;
-//             // sell order more than buy order quantity, remaining sell quantity to add to order book (redis)
-//         if sell_orders[index].quantity > order.quantity {
-//             sell_orders[index].quantity -= order.quantity;
-//         }
-//             // sell order equal to buy order quantity, remove sell order from order book (redis) 
-//         else if sell_orders[index].quantity == order.quantity {
-//             sell_orders.remove(index);
-//         }
-//             // sell order less than buy order quantity, remaining buy quantity to add to order book (redis); if partial_fill is false, then this should not be able to happen
-//         else if sell_orders[index].quantity < order.quantity {
-//             order.quantity -= sell_orders[index].quantity;
-//             buy_orders.push(order.clone());
-//             // sort the buy orders in descending order of price
-//             buy_orders.sort_by(|a, b| b.price.partial_cmp(&a.price).unwrap());
-//             // update the buy orders in Redis
-//             let to_redis_buy_orders_string = to_string(&buy_orders).expect("Failed to serialize buy orders");
-//             conn.hset(order_book_key, "buy_orders", to_redis_buy_orders_string).await?;
-            
-//             trade_quantity = sell_orders[index].quantity;  // Because the sell order quantity is less than the buy order quantity
-            
-//             sell_orders.remove(index);
-//         }
-
-//         // Create a trade
-//         let trade = Trade {
-//             buy_order_id: order.id.clone(),
-//             sell_order_id: matching_order.id.clone(),
-//             stock_symbol: order.stock_symbol.clone(),
-//             quantity: trade_quantity,
-//             price: order.price,   // Take order because it is the buy order with the higher price
-//             timestamp: order.timestamp,
-//         };
-
-//         // Update the sell orders in Redis
-//         let to_redis_sell_orders_string = to_string(&sell_orders).expect("Failed to serialize sell orders");
-//         conn.hset(order_book_key, "sell_orders", to_redis_sell_orders_string).await?;
-
-//         return Ok(Some(trade));
-//     } else {
-//         buy_orders.push(order.clone());
-
-//         // Sort the buy orders in descending order of price
-//         buy_orders.sort_by(|a, b| b.price.partial_cmp(&a.price).unwrap());
-
-//         // Update the buy orders in Redis
-//         let to_redis_buy_orders_string = to_string(&buy_orders).expect("Failed to serialize buy orders");
-//         conn.hset(order_book_key, "buy_orders", to_redis_buy_orders_string).await?;
-
-//         return Ok(None);
-//     }
-// }
-
-// async fn handle_sell_order(
-//     conn: &mut aio::MultiplexedConnection,
-//     order_book_key: &str,
-//     mut order: Order,
-//     mut sell_orders: Vec<Order>,
-//     mut buy_orders: Vec<Order>,
-// ) -> RedisResult<Option<Trade>> {
-//     if let Some((index, matching_order)) = buy_orders
-//         .iter()  // Iterate over the buy orders
-//         .enumerate()  // Enumerate the buy orders
-//         .find(|(_, buy_order)| {
-//             buy_order.price >= order.price &&  // Find the first buy order with a price greater than or equal to the sell order price
-//             (order.partial_fill || buy_order.quantity >= order.quantity)  // Additional filter: check quantity only if partial_fill is false; must make sure that the buy order quantity is greater than or equal to the sell order quantity
-//         })
-//         .map(|(index, buy_order)| (index, buy_order.clone())) // Clone the matching order to avoid borrowing issues
-//     {
-//         // Update the buy orders in Redis
-//         let mut trade_quantity = order.quantity;
-//             // buy order more than sell order quantity, remaining buy quantity to add to order book (redis)
-//         if buy_orders[index].quantity > order.quantity {
-//             buy_orders[index].quantity -= order.quantity;
-//         }
-//             // buy order equal to sell order quantity, remove buy order from order book (redis) 
-//         else if buy_orders[index].quantity == order.quantity {
-//             buy_orders.remove(index);
-//         }
-//             // buy order less than sell order quantity, remaining sell quantity to add to order book (redis); if partial_fill is false, then this should not be able to happen
-//         else if buy_orders[index].quantity < order.quantity {
-//             order.quantity -= buy_orders[index].quantity;
-//             sell_orders.push(order.clone());
-//             // sort the sell orders in ascending order of price
-//             sell_orders.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap());
-//             // update the sell orders in Redis
-//             let to_redis_sell_orders_string = to_string(&sell_orders).expect("Failed to serialize sell orders");
-//             conn.hset(order_book_key, "sell_orders", to_redis_sell_orders_string).await?;
-            
-//             trade_quantity = buy_orders[index].quantity;  // Because the buy order quantity is less than the sell order quantity
-            
-//             buy_orders.remove(index);
-//         }
-
-//         // Create a trade
-//         let trade = Trade {
-//             buy_order_id: matching_order.id.clone(),
-//             sell_order_id: order.id.clone(),
-//             stock_symbol: order.stock_symbol.clone(),
-//             quantity: trade_quantity,
-//             price: matching_order.price,   // Take matching order because it is the buy order with the higher price
-//             timestamp: order.timestamp,
-//         };
-
-//         // Update the buy orders in Redis
-//         let to_redis_buy_orders_string = to_string(&buy_orders).expect("Failed to serialize buy orders");
-//         conn.hset(order_book_key, "buy_orders", to_redis_buy_orders_string).await?;
-
-//         return Ok(Some(trade));
-//     } else {
-//         sell_orders.push(order.clone());
-
-//         // Sort the sell orders in ascending order of price
-//         sell_orders.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap());
-
-//         // Update the sell orders in Redis
-//         let to_redis_sell_orders_string = to_string(&sell_orders).expect("Failed to serialize sell orders");
-//         conn.hset(order_book_key, "sell_orders", to_redis_sell_orders_string).await?;
-
-//         return Ok(None);
-//     }
-// }
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs()
+}
 
+// The first session close strictly after now
+fn next_session_close(now: u64, session_close: u64) -> u64 {
+    let today_close = now - now % SECONDS_PER_DAY + session_close;
+    if today_close > now {
+        today_close
+    } else {
+        today_close + SECONDS_PER_DAY
+    }
+}
\ No newline at end of file
@@ -0,0 +1,101 @@
+use common::models::Trade;
+use common::storage::SavedOrderBook;
+use serde_json::to_value;
+use std::collections::VecDeque;
+use std::io;
+
+use crate::journal::{read_journal, JournalEntry, JournalEvent};
+use crate::matching_engine::MatchingEngine;
+use crate::order_book::OrderBook;
+
+// Order books rebuilt from the journal of the order book manager, checking that replaying it gives the same trades
+#[derive(Default)]
+pub struct Replay {
+    // None until the first Started entry
+    pub engine: Option<MatchingEngine>,
+    // Trades of the last replayed event that the journal hasn't shown yet
+    pending_trades: VecDeque<Trade>,
+    last_sequence: u64,
+    pub events: usize,
+    pub trades: usize,
+    // Everything the replay didn't agree with, empty if the journal replays to the same trades
+    pub mismatches: Vec<String>,
+}
+
+// Replay every complete entry of the journal, a last line cut off by a crash is left out like Journal::open does
+pub fn replay_journal(path: &str) -> io::Result<Replay> {
+    let entries = read_journal(path)?;
+    println!("Replay: Read {} entries from {}", entries.len(), path);
+
+    let mut replay = Replay::default();
+    for entry in entries {
+        replay.apply(entry);
+    }
+    replay.finish();
+    Ok(replay)
+}
+
+impl Replay {
+    // Give the event of the entry to the rebuilt matching engine, or check the trade of the entry against the replayed ones
+    pub fn apply(&mut self, entry: JournalEntry) {
+        if entry.sequence != self.last_sequence + 1 {
+            self.mismatches.push(format!("Entry {} follows entry {}", entry.sequence, self.last_sequence));
+        }
+        self.last_sequence = entry.sequence;
+
+        // Every trade entry is checked against the next trade the replayed event made
+        if let JournalEvent::Trade(trade) = entry.event {
+            match self.pending_trades.pop_front() {
+                Some(replayed) if to_value(&replayed).ok() == to_value(&trade).ok() => self.trades += 1,
+                Some(replayed) => self.mismatches.push(format!("Entry {}: journal has {:?}, replay made {:?}", entry.sequence, trade, replayed)),
+                None => self.mismatches.push(format!("Entry {}: journal has {:?}, replay made no trade", entry.sequence, trade)),
+            }
+            return;
+        }
+        self.finish();
+        self.events += 1;
+
+        let timestamp = entry.timestamp;
+        let events = match (entry.event, self.engine.as_mut()) {
+            (JournalEvent::Started { settings, next_sequence, market_halted_until, order_books, restored }, engine) => {
+                // A restart loads the order books from the storage, they must be the ones the replay got to
+                // unless setup replaced them in between, then the replay starts over from them
+                if restored {
+                    println!("Replay: Entry {}: order books were restored or reset, starting over from them", entry.sequence);
+                } else if let Some(engine) = engine {
+                    let replayed: Vec<SavedOrderBook> = engine.order_books().values().map(OrderBook::saved).collect();
+                    if to_value(&replayed).ok() != to_value(&order_books).ok() {
+                        self.mismatches.push(format!("Entry {}: order books loaded at restart differ from the replayed ones", entry.sequence));
+                    }
+                }
+
+                let order_books = order_books
+                    .into_iter()
+                    .map(|saved| (saved.stock_symbol.clone(), OrderBook::from_saved(saved)))
+                    .collect();
+                self.engine = Some(MatchingEngine::new(order_books, next_sequence, market_halted_until, settings, timestamp));
+                return;
+            }
+            (_, None) => {
+                self.mismatches.push(format!("Entry {}: event before the order book manager started", entry.sequence));
+                return;
+            }
+            (JournalEvent::NewOrder(order), Some(engine)) => engine.accept_order(order, timestamp),
+            (JournalEvent::Cancel(request), Some(engine)) => engine.cancel_order(request, timestamp),
+            (JournalEvent::Replace(request), Some(engine)) => engine.replace_order(request, timestamp),
+            (JournalEvent::LastPrice(stock), Some(engine)) => engine.update_last_price(&stock, timestamp),
+            (JournalEvent::SessionPhase, Some(engine)) => engine.update_session_phase(timestamp),
+            (JournalEvent::Expire, Some(engine)) => engine.expire_orders(timestamp),
+            (JournalEvent::Trade(_), Some(_)) => unreachable!("Trades are checked above"),
+        };
+
+        self.pending_trades.extend(events.trades);
+    }
+
+    // Trades the replay made that the journal doesn't have, call it after the last entry
+    pub fn finish(&mut self) {
+        for trade in self.pending_trades.drain(..) {
+            self.mismatches.push(format!("Replay made {:?}, the journal doesn't have it", trade));
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use common::models::{Order, OrderKind, ReferenceData, RejectReason, ReplaceRequest, TimeInForce, TrailingOffset};
+use common::price::Price;
+
+// How far a limit or stop price may be from the current stock price, 0.5 = 50% above or below
+const MAX_PRICE_DEVIATION: f64 = 0.5;
+
+// Check everything about a new order that doesn't need its order book, before it is accepted
+// stock_price is the current price of the stock in stocks:prices, None if the stock is not there
+// The tick size, lot size and minimum quantity are only checked for a stock with reference data
+pub fn validate_order(order: &Order, stock_price: Option<Price>, reference_data: Option<&ReferenceData>, now: u64) -> Result<(), RejectReason> {
+    if order.quantity == 0 {
+        return Err(RejectReason::ZeroQuantity);
+    }
+
+    let Some(stock_price) = stock_price else {
+        return Err(RejectReason::UnknownSymbol);
+    };
+
+    // The price is ignored for market and stop orders
+    // A pegged order keeps its price while there is nothing to peg to, so it is checked like any limit price
+    let uses_price = matches!(order.kind, OrderKind::Limit | OrderKind::StopLimit);
+    if uses_price {
+        if !is_valid_price(order.price) {
+            return Err(RejectReason::InvalidPrice);
+        }
+        validate_price_range(order.price, stock_price)?;
+    }
+
+    match (order.kind, order.stop_price) {
+        (OrderKind::Stop | OrderKind::StopLimit, None) => return Err(RejectReason::MissingStopPrice),
+        (_, Some(stop_price)) if !is_valid_price(stop_price) => return Err(RejectReason::InvalidStopPrice),
+        (OrderKind::Stop | OrderKind::StopLimit, Some(stop_price)) => validate_price_range(stop_price, stock_price)?,
+        _ => {}
+    }
+
+    if order.kind == OrderKind::TrailingStop {
+        match order.trailing_offset {
+            None => return Err(RejectReason::MissingTrailingOffset),
+            // The trigger level may be as far from the stock price as a stop price, so a sell trailing stop stays above 0
+            Some(TrailingOffset::Amount(offset)) if !is_valid_price(offset) || offset.to_f64() > stock_price.to_f64() * MAX_PRICE_DEVIATION => {
+                return Err(RejectReason::InvalidTrailingOffset)
+            }
+            // Also catches NaN, every comparison with NaN is false
+            Some(TrailingOffset::Percent(percent)) if !(percent.is_finite() && percent > 0.0 && percent <= MAX_PRICE_DEVIATION * 100.0) => {
+                return Err(RejectReason::InvalidTrailingOffset)
+            }
+            Some(_) => {}
+        }
+    }
+
+    // An iceberg order trades one peak at a time, so it can't be all-or-none
+    match order.display_quantity {
+        Some(0) => return Err(RejectReason::InvalidDisplayQuantity),
+        Some(_) if !order.partial_fill => return Err(RejectReason::IcebergAllOrNone),
+        _ => {}
+    }
+
+    if (order.post_only.is_some() || order.peg.is_some()) && order.kind != OrderKind::Limit {
+        return Err(RejectReason::NotLimitOrder);
+    }
+    // The pegged price may be as far from the stock price as any limit price
+    if order.peg.is_some_and(|peg| peg.offset.to_f64().abs() > stock_price.to_f64() * MAX_PRICE_DEVIATION) {
+        return Err(RejectReason::InvalidPegOffset);
+    }
+
+    if let Some(reference_data) = reference_data {
+        if order.quantity < reference_data.min_quantity {
+            return Err(RejectReason::BelowMinimumQuantity);
+        }
+        if !is_lot(order.quantity, reference_data) || order.display_quantity.is_some_and(|display| !is_lot(display, reference_data)) {
+            return Err(RejectReason::InvalidLotSize);
+        }
+        let stop_price_on_tick = order.stop_price.map_or(true, |stop_price| is_on_tick(stop_price, reference_data));
+        if (uses_price && !is_on_tick(order.price, reference_data)) || !stop_price_on_tick {
+            return Err(RejectReason::InvalidTickSize);
+        }
+    }
+
+    // Only DAY and GTD orders have an expire time, a GTD order that already expired never reaches the book
+    if order.time_in_force == TimeInForce::GoodTillDate && order.expire_time.is_none() {
+        return Err(RejectReason::MissingExpireTime);
+    }
+    if order.expire_time.is_some_and(|expire_time| expire_time <= now) {
+        return Err(RejectReason::AlreadyExpired);
+    }
+
+    Ok(())
+}
+
+// Check the new quantity and price of a cancel/replace request, the minimum quantity doesn't apply as the order may be partly filled
+pub fn validate_replace(request: &ReplaceRequest, reference_data: Option<&ReferenceData>) -> Result<(), RejectReason> {
+    // A replace to 0 should be a cancel instead
+    if request.quantity == 0 {
+        return Err(RejectReason::ZeroQuantity);
+    }
+    if !is_valid_price(request.price) {
+        return Err(RejectReason::InvalidPrice);
+    }
+
+    if let Some(reference_data) = reference_data {
+        if !is_lot(request.quantity, reference_data) {
+            return Err(RejectReason::InvalidLotSize);
+        }
+        if !is_on_tick(request.price, reference_data) {
+            return Err(RejectReason::InvalidTickSize);
+        }
+    }
+
+    Ok(())
+}
+
+// The new price of a cancel/replace request must be as near the current stock price as the price of a new order
+// stock_price is the current price of the stock in stocks:prices, None if the stock is not there
+pub fn validate_replace_price(request: &ReplaceRequest, stock_price: Option<Price>) -> Result<(), RejectReason> {
+    let Some(stock_price) = stock_price else {
+        return Err(RejectReason::UnknownSymbol);
+    };
+    validate_price_range(request.price, stock_price)
+}
+
+// A limit or stop price may only be MAX_PRICE_DEVIATION away from the current stock price
+fn validate_price_range(price: Price, stock_price: Price) -> Result<(), RejectReason> {
+    if (price - stock_price).to_f64().abs() > stock_price.to_f64() * MAX_PRICE_DEVIATION {
+        return Err(RejectReason::PriceOutOfRange);
+    }
+    Ok(())
+}
+
+fn is_valid_price(price: Price) -> bool {
+    price > Price::ZERO
+}
+
+// Exact now that prices are fixed-point, a tick size of 0 (not set properly) allows every price
+fn is_on_tick(price: Price, reference_data: &ReferenceData) -> bool {
+    reference_data.tick_size == Price::ZERO || price.0 % reference_data.tick_size.0 == 0
+}
+
+fn is_lot(quantity: u32, reference_data: &ReferenceData) -> bool {
+    reference_data.lot_size == 0 || quantity % reference_data.lot_size == 0
+}
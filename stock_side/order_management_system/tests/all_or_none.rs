@@ -0,0 +1,119 @@
+mod support;
+
+use common::models::{Order, OrderType};
+use common::price::Price;
+use order_management_system::order_book::OrderBook;
+
+fn order(sequence: u64, order_type: OrderType, quantity: u32, price: f64, partial_fill: bool) -> Order {
+    Order { partial_fill, ..support::order(sequence, order_type, quantity, price) }
+}
+
+#[test]
+fn incoming_all_or_none_buy_rests_when_it_cannot_be_filled_completely() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Sell, 50, 100.0, true));
+
+    let result = order_book.match_order(order(2, OrderType::Buy, 80, 100.0, false));
+
+    assert!(result.trades.is_empty());
+    assert!(result.cancelled.is_none());
+    assert_eq!(order_book.buy_orders()[0].quantity, 80);
+    assert_eq!(order_book.sell_orders()[0].quantity, 50);
+}
+
+#[test]
+fn incoming_all_or_none_buy_fills_across_levels_when_enough_liquidity() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Sell, 50, 100.0, true));
+    order_book.match_order(order(2, OrderType::Sell, 50, 101.0, true));
+
+    let result = order_book.match_order(order(3, OrderType::Buy, 80, 101.0, false));
+
+    let filled: Vec<(u32, f64)> = result.trades.iter().map(|trade| (trade.quantity, trade.price.to_f64())).collect();
+    assert_eq!(filled, vec![(50, 100.0), (30, 101.0)]);
+    assert!(order_book.buy_orders().is_empty());
+    assert_eq!(order_book.sell_orders()[0].quantity, 20);
+}
+
+#[test]
+fn incoming_all_or_none_sell_rests_when_it_cannot_be_filled_completely() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Buy, 50, 100.0, true));
+
+    let result = order_book.match_order(order(2, OrderType::Sell, 80, 100.0, false));
+
+    assert!(result.trades.is_empty());
+    assert_eq!(order_book.sell_orders()[0].quantity, 80);
+    assert_eq!(order_book.buy_orders()[0].quantity, 50);
+}
+
+#[test]
+fn incoming_all_or_none_sell_fills_when_enough_liquidity() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Buy, 60, 100.0, true));
+    order_book.match_order(order(2, OrderType::Buy, 60, 99.0, true));
+
+    let result = order_book.match_order(order(3, OrderType::Sell, 100, 99.0, false));
+
+    let filled: Vec<(u32, f64)> = result.trades.iter().map(|trade| (trade.quantity, trade.price.to_f64())).collect();
+    assert_eq!(filled, vec![(60, 100.0), (40, 99.0)]);
+    assert!(order_book.sell_orders().is_empty());
+}
+
+#[test]
+fn resting_all_or_none_sell_is_skipped_by_a_smaller_buy_and_keeps_its_place() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Sell, 100, 100.0, false));
+    order_book.match_order(order(2, OrderType::Sell, 30, 100.0, true));
+
+    let result = order_book.match_order(order(3, OrderType::Buy, 40, 100.0, true));
+
+    assert_eq!(result.trades.len(), 1);
+    assert_eq!(result.trades[0].sell_order_id, "order-2");
+    assert_eq!(result.trades[0].quantity, 30);
+    // The all-or-none sell is still first in line and untouched, the rest of the buy waits behind it
+    assert_eq!(order_book.sell_orders()[0].id, "order-1");
+    assert_eq!(order_book.sell_orders()[0].quantity, 100);
+    assert_eq!(order_book.buy_orders()[0].quantity, 10);
+}
+
+#[test]
+fn resting_all_or_none_sell_is_filled_by_a_large_enough_buy() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Sell, 100, 100.0, false));
+
+    let result = order_book.match_order(order(2, OrderType::Buy, 120, 100.0, true));
+
+    assert_eq!(result.trades.len(), 1);
+    assert_eq!(result.trades[0].quantity, 100);
+    assert!(order_book.sell_orders().is_empty());
+    assert_eq!(order_book.buy_orders()[0].quantity, 20);
+}
+
+#[test]
+fn resting_all_or_none_buy_is_skipped_by_a_smaller_sell() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Buy, 100, 100.0, false));
+    order_book.match_order(order(2, OrderType::Buy, 30, 99.0, true));
+
+    let result = order_book.match_order(order(3, OrderType::Sell, 50, 99.0, true));
+
+    assert_eq!(result.trades.len(), 1);
+    assert_eq!(result.trades[0].buy_order_id, "order-2");
+    assert_eq!(result.trades[0].price, Price::from_f64(99.0));
+    assert_eq!(order_book.buy_orders()[0].quantity, 100);
+    assert_eq!(order_book.sell_orders()[0].quantity, 20);
+}
+
+#[test]
+fn resting_all_or_none_buy_is_filled_by_a_large_enough_sell() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Buy, 100, 100.0, false));
+
+    let result = order_book.match_order(order(2, OrderType::Sell, 100, 100.0, false));
+
+    assert_eq!(result.trades.len(), 1);
+    assert_eq!(result.trades[0].quantity, 100);
+    assert!(order_book.buy_orders().is_empty());
+    assert!(order_book.sell_orders().is_empty());
+}
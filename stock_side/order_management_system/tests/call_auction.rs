@@ -0,0 +1,129 @@
+mod support;
+
+use common::models::{Order, OrderKind, OrderType, RejectReason, SessionPhase};
+use common::price::Price;
+use order_management_system::matching_engine::{CallAuction, EngineSettings, MatchingEngine, TradingHours};
+use order_management_system::order_book::OrderBook;
+use std::collections::BTreeMap;
+use support::{order, settings};
+
+// Buys 100 @ 101 and 50 @ 100, sells 80 @ 99 and 60 @ 100: 140 can trade at 100 with 10 left on the buy side
+fn pre_open_order_book() -> OrderBook {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.set_phase(SessionPhase::PreOpen);
+    order_book.match_order(order(1, OrderType::Buy, 100, 101.0));
+    order_book.match_order(order(2, OrderType::Buy, 50, 100.0));
+    order_book.match_order(order(3, OrderType::Sell, 80, 99.0));
+    order_book.match_order(order(4, OrderType::Sell, 60, 100.0));
+    order_book
+}
+
+#[test]
+fn orders_accumulate_during_the_call_with_an_indicative_price() {
+    let order_book = pre_open_order_book();
+
+    assert_eq!(order_book.buy_orders().len(), 2);
+    assert_eq!(order_book.sell_orders().len(), 2);
+
+    let auction_info = order_book.auction_info(0);
+    assert_eq!(auction_info.indicative_price, Some(Price::from_f64(100.0)));
+    assert_eq!(auction_info.matched_quantity, 140);
+    assert_eq!(auction_info.imbalance_quantity, 10);
+    assert_eq!(auction_info.imbalance_side, Some(OrderType::Buy));
+}
+
+#[test]
+fn uncross_trades_everything_at_the_equilibrium_price() {
+    let mut order_book = pre_open_order_book();
+    order_book.set_phase(SessionPhase::Continuous);
+
+    let result = order_book.uncross();
+
+    let filled: Vec<(&str, &str, u32)> = result
+        .trades
+        .iter()
+        .map(|trade| (trade.buy_order_id.as_str(), trade.sell_order_id.as_str(), trade.quantity))
+        .collect();
+    assert_eq!(filled, vec![("order-1", "order-3", 80), ("order-1", "order-4", 20), ("order-2", "order-4", 40)]);
+    assert!(result.trades.iter().all(|trade| trade.price == Price::from_f64(100.0)));
+    assert_eq!(order_book.last_price(), Some(Price::from_f64(100.0)));
+
+    assert!(order_book.sell_orders().is_empty());
+    assert_eq!(order_book.buy_orders().len(), 1);
+    assert_eq!(order_book.buy_orders()[0].id, "order-2");
+    assert_eq!(order_book.buy_orders()[0].quantity, 10);
+}
+
+#[test]
+fn iceberg_with_a_refreshed_peak_goes_behind_the_orders_at_its_price() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.set_phase(SessionPhase::PreOpen);
+    order_book.match_order(Order { display_quantity: Some(20), ..order(1, OrderType::Buy, 100, 100.0) });
+    order_book.match_order(order(2, OrderType::Buy, 50, 100.0));
+    order_book.match_order(order(3, OrderType::Sell, 30, 100.0));
+    order_book.set_phase(SessionPhase::Continuous);
+
+    // The iceberg trades more than its peak in one go, then shows a new one
+    let result = order_book.uncross();
+    assert_eq!(result.trades.len(), 1);
+    assert_eq!(result.trades[0].buy_order_id, "order-1");
+
+    let buy_orders = order_book.buy_orders();
+    let queue: Vec<(&str, u32)> = buy_orders.iter().map(|order| (order.id.as_str(), order.quantity)).collect();
+    assert_eq!(queue, vec![("order-2", 50), ("order-1", 70)]);
+    assert_eq!(buy_orders[1].visible_quantity, 20);
+}
+
+#[test]
+fn tie_is_broken_by_the_last_price() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.set_phase(SessionPhase::PreOpen);
+    order_book.match_order(order(1, OrderType::Buy, 100, 102.0));
+    order_book.match_order(order(2, OrderType::Sell, 100, 98.0));
+
+    // 100 trades at both 98 and 102 with no imbalance, without a last price the lowest one is taken
+    assert_eq!(order_book.auction_info(0).indicative_price, Some(Price::from_f64(98.0)));
+
+    order_book.set_last_price(Price::from_f64(101.0));
+    assert_eq!(order_book.auction_info(0).indicative_price, Some(Price::from_f64(102.0)));
+}
+
+const HOUR: u64 = 60 * 60;
+
+// Open 13:00-21:00 with a closing call from 20:00
+fn scheduled_engine(now: u64) -> MatchingEngine {
+    let settings = EngineSettings {
+        call_auctions: vec![CallAuction { phase: SessionPhase::PreClose, start: 20 * HOUR, end: 21 * HOUR }],
+        trading_hours: Some(TradingHours { open: 13 * HOUR, close: 21 * HOUR }),
+        ..settings()
+    };
+    MatchingEngine::new(BTreeMap::new(), 1, 0, settings, now)
+}
+
+#[test]
+fn closing_call_is_uncrossed_at_the_close_and_nothing_trades_until_the_open() {
+    let mut engine = scheduled_engine(20 * HOUR);
+    engine.accept_order(order(1, OrderType::Buy, 100, 100.0), 20 * HOUR);
+    let events = engine.accept_order(order(2, OrderType::Sell, 100, 100.0), 20 * HOUR);
+    assert!(events.trades.is_empty());
+
+    let events = engine.update_session_phase(21 * HOUR);
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(engine.order_books()["AAPL"].phase(), SessionPhase::Closed);
+
+    // Limit orders wait for the open, orders that can't wait are rejected
+    let mut market_order = order(3, OrderType::Buy, 10, 100.0);
+    market_order.kind = OrderKind::Market;
+    assert_eq!(engine.prepare_order(&mut market_order, 22 * HOUR), Err(RejectReason::MarketClosed));
+
+    engine.accept_order(order(4, OrderType::Buy, 10, 101.0), 22 * HOUR);
+    let events = engine.accept_order(order(5, OrderType::Sell, 10, 99.0), 22 * HOUR);
+    assert!(events.trades.is_empty());
+    assert!(engine.update_session_phase(HOUR).trades.is_empty());
+
+    // Without a pre-open call they are uncrossed when the market opens
+    let events = engine.update_session_phase(13 * HOUR);
+    assert_eq!(engine.order_books()["AAPL"].phase(), SessionPhase::Continuous);
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(events.trades[0].quantity, 10);
+}
@@ -0,0 +1,90 @@
+mod support;
+
+use common::models::{AckStatus, CancelRequest, OrderType, RejectReason, ReplaceRequest};
+use common::price::Price;
+use order_management_system::matching_engine::MatchingEngine;
+use support::{engine, fills, order};
+
+fn replace(order_id: &str, quantity: u32, price: f64) -> ReplaceRequest {
+    ReplaceRequest {
+        id: format!("replace-{}", order_id),
+        order_id: order_id.to_string(),
+        stock_symbol: "AAPL".to_string(),
+        quantity,
+        price: Price::from_f64(price),
+        timestamp: 0,
+    }
+}
+
+fn sell_order_ids(engine: &MatchingEngine) -> Vec<String> {
+    engine.order_books()["AAPL"].sell_orders().into_iter().map(|order| order.id).collect()
+}
+
+#[test]
+fn reducing_the_quantity_keeps_the_time_priority() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 100.0), 0);
+    engine.accept_order(order(2, OrderType::Sell, 10, 100.0), 0);
+
+    let events = engine.replace_order(replace("order-1", 5, 100.0), 1);
+    assert_eq!(events.ack.unwrap().status, AckStatus::Accepted);
+    assert_eq!(sell_order_ids(&engine), vec!["order-1", "order-2"]);
+    assert_eq!(engine.order_books()["AAPL"].sell_orders()[0].quantity, 5);
+
+    let events = engine.accept_order(order(3, OrderType::Buy, 5, 100.0), 2);
+    assert_eq!(fills(&events.trades), vec![("order-3", "order-1", 5, 100.0)]);
+}
+
+#[test]
+fn raising_the_quantity_or_changing_the_price_loses_the_time_priority() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 100.0), 0);
+    engine.accept_order(order(2, OrderType::Sell, 10, 100.0), 0);
+    engine.accept_order(order(3, OrderType::Sell, 10, 100.0), 0);
+
+    engine.replace_order(replace("order-1", 20, 100.0), 1);
+    assert_eq!(sell_order_ids(&engine), vec!["order-2", "order-3", "order-1"]);
+    assert_eq!(engine.order_books()["AAPL"].sell_orders()[2].sequence, 4);
+
+    // Away and back again, still behind the orders that never moved
+    engine.replace_order(replace("order-2", 10, 100.5), 2);
+    engine.replace_order(replace("order-2", 10, 100.0), 3);
+    assert_eq!(sell_order_ids(&engine), vec!["order-3", "order-1", "order-2"]);
+}
+
+#[test]
+fn replacing_to_a_crossing_price_trades_like_a_new_order() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 101.0), 0);
+    engine.accept_order(order(2, OrderType::Buy, 10, 99.0), 0);
+
+    let events = engine.replace_order(replace("order-2", 10, 101.0), 1);
+
+    assert_eq!(fills(&events.trades), vec![("order-2", "order-1", 10, 101.0)]);
+    assert!(engine.order_books()["AAPL"].buy_orders().is_empty());
+}
+
+#[test]
+fn cancelled_order_leaves_the_book_and_cannot_be_cancelled_twice() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 100.0), 0);
+    engine.accept_order(order(2, OrderType::Sell, 10, 100.0), 0);
+    let cancel = CancelRequest {
+        id: "cancel-1".to_string(),
+        order_id: "order-1".to_string(),
+        stock_symbol: "AAPL".to_string(),
+        timestamp: 0,
+    };
+
+    let events = engine.cancel_order(cancel.clone(), 1);
+    assert_eq!(events.ack.unwrap().status, AckStatus::Accepted);
+    assert_eq!(sell_order_ids(&engine), vec!["order-2"]);
+
+    let events = engine.cancel_order(cancel, 2);
+    let ack = events.ack.unwrap();
+    assert_eq!(ack.status, AckStatus::Rejected);
+    assert_eq!(ack.reject_reason, Some(RejectReason::OrderNotFound));
+
+    let events = engine.replace_order(replace("order-1", 5, 100.0), 3);
+    assert_eq!(events.ack.unwrap().reject_reason, Some(RejectReason::OrderNotFound));
+}
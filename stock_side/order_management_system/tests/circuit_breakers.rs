@@ -0,0 +1,136 @@
+mod support;
+
+use common::models::{Order, OrderType, ReferenceData, SessionPhase, Stock};
+use common::price::Price;
+use order_management_system::matching_engine::{CircuitBreakers, EngineSettings, MatchingEngine};
+use order_management_system::order_book::OrderBook;
+use std::collections::{BTreeMap, HashMap};
+
+const NOW: u64 = 1_000;
+
+fn order(stock_symbol: &str, sequence: u64, order_type: OrderType, price: f64) -> Order {
+    Order { id: format!("{}-{}", stock_symbol, sequence), stock_symbol: stock_symbol.to_string(), ..support::order(sequence, order_type, 10, price) }
+}
+
+fn engine(circuit_breakers: CircuitBreakers, reference_data: HashMap<String, ReferenceData>) -> MatchingEngine {
+    let settings = EngineSettings { reference_data, circuit_breakers, ..support::settings() };
+    MatchingEngine::new(BTreeMap::new(), 1, 0, settings, NOW)
+}
+
+// A sell and a buy that trade 10 at the price
+fn trade_at(engine: &mut MatchingEngine, stock_symbol: &str, sequence: u64, price: f64) -> usize {
+    engine.accept_order(order(stock_symbol, sequence, OrderType::Sell, price), NOW);
+    engine.accept_order(order(stock_symbol, sequence + 1, OrderType::Buy, price), NOW).trades.len()
+}
+
+fn phase(engine: &MatchingEngine, stock_symbol: &str) -> SessionPhase {
+    engine.order_books()[stock_symbol].phase()
+}
+
+// The engine started again from its saved order books, as after a restart
+fn restarted(engine: &MatchingEngine, now: u64) -> MatchingEngine {
+    let order_books = engine
+        .order_books()
+        .values()
+        .map(|order_book| (order_book.symbol().to_string(), OrderBook::from_saved(order_book.saved())))
+        .collect();
+    MatchingEngine::new(order_books, engine.next_sequence(), engine.market_halted_until(), engine.settings().clone(), now)
+}
+
+#[test]
+fn stock_leaving_its_price_band_is_halted_until_the_reopening_auction() {
+    let mut engine = engine(CircuitBreakers { price_band: 0.1, market_wide_move: 0.5, cool_down: 300 }, HashMap::new());
+    assert_eq!(trade_at(&mut engine, "AAPL", 1, 100.0), 1);
+
+    engine.accept_order(order("AAPL", 3, OrderType::Sell, 115.0), NOW);
+    let events = engine.accept_order(order("AAPL", 4, OrderType::Buy, 115.0), NOW);
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(phase(&engine, "AAPL"), SessionPhase::Halted);
+    assert_eq!(events.status.len(), 1);
+    assert_eq!(events.status[0].resume_time, Some(NOW + 300));
+    assert!(events.status[0].reason.is_some());
+
+    // Orders wait for the reopening auction
+    assert_eq!(trade_at(&mut engine, "AAPL", 5, 120.0), 0);
+    let events = engine.update_session_phase(NOW + 299);
+    assert!(events.status.is_empty());
+    assert!(events.trades.is_empty());
+
+    let events = engine.update_session_phase(NOW + 300);
+    assert_eq!(phase(&engine, "AAPL"), SessionPhase::Continuous);
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(events.trades[0].price, Price::from_f64(120.0));
+    // The band starts again from the reopening price
+    assert_eq!(engine.order_books()["AAPL"].reference_price(), Some(Price::from_f64(120.0)));
+}
+
+#[test]
+fn price_band_of_the_reference_data_comes_first() {
+    let reference_data = ReferenceData { tick_size: Price::from_f64(0.01), lot_size: 1, min_quantity: 1, price_band: Some(0.2) };
+    let mut engine = engine(
+        CircuitBreakers { price_band: 0.1, market_wide_move: 0.5, cool_down: 300 },
+        HashMap::from([("AAPL".to_string(), reference_data)]),
+    );
+    trade_at(&mut engine, "AAPL", 1, 100.0);
+
+    trade_at(&mut engine, "AAPL", 3, 115.0);
+    assert_eq!(phase(&engine, "AAPL"), SessionPhase::Continuous);
+
+    trade_at(&mut engine, "AAPL", 5, 126.0);
+    assert_eq!(phase(&engine, "AAPL"), SessionPhase::Halted);
+}
+
+#[test]
+fn market_wide_move_halts_every_stock() {
+    let mut engine = engine(CircuitBreakers { price_band: 0.5, market_wide_move: 0.1, cool_down: 300 }, HashMap::new());
+    trade_at(&mut engine, "AAPL", 1, 100.0);
+    trade_at(&mut engine, "MSFT", 1, 100.0);
+
+    // 12.5% on average, inside the band of each stock
+    engine.update_last_price(&Stock { symbol: "AAPL".to_string(), price: Price::from_f64(115.0) }, NOW);
+    engine.update_last_price(&Stock { symbol: "MSFT".to_string(), price: Price::from_f64(110.0) }, NOW);
+    assert_eq!(phase(&engine, "AAPL"), SessionPhase::Continuous);
+
+    let events = engine.update_session_phase(NOW);
+    assert_eq!(events.status.len(), 2);
+    assert_eq!(phase(&engine, "AAPL"), SessionPhase::Halted);
+    assert_eq!(phase(&engine, "MSFT"), SessionPhase::Halted);
+
+    // An order book created during the halt starts halted
+    assert_eq!(trade_at(&mut engine, "GOOG", 1, 50.0), 0);
+    assert_eq!(phase(&engine, "GOOG"), SessionPhase::Halted);
+
+    let events = engine.update_session_phase(NOW + 300);
+    assert!(engine.order_books().values().all(|order_book| order_book.phase() == SessionPhase::Continuous));
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(events.trades[0].stock_symbol, "GOOG");
+}
+
+#[test]
+fn halted_stock_stays_halted_after_a_restart() {
+    let mut engine = engine(CircuitBreakers { price_band: 0.1, market_wide_move: 0.5, cool_down: 300 }, HashMap::new());
+    trade_at(&mut engine, "AAPL", 1, 100.0);
+    trade_at(&mut engine, "AAPL", 3, 115.0);
+    assert_eq!(trade_at(&mut engine, "AAPL", 5, 120.0), 0);
+
+    let mut engine = restarted(&engine, NOW + 10);
+    assert_eq!(phase(&engine, "AAPL"), SessionPhase::Halted);
+    assert_eq!(engine.order_books()["AAPL"].halted_until(), Some(NOW + 300));
+
+    let events = engine.update_session_phase(NOW + 300);
+    assert_eq!(phase(&engine, "AAPL"), SessionPhase::Continuous);
+    assert_eq!(events.trades.len(), 1);
+}
+
+#[test]
+fn market_wide_halt_survives_a_restart() {
+    let mut engine = engine(CircuitBreakers { price_band: 0.5, market_wide_move: 0.1, cool_down: 300 }, HashMap::new());
+    trade_at(&mut engine, "AAPL", 1, 100.0);
+    engine.update_last_price(&Stock { symbol: "AAPL".to_string(), price: Price::from_f64(115.0) }, NOW);
+    engine.update_session_phase(NOW);
+
+    let mut engine = restarted(&engine, NOW + 10);
+    assert_eq!(phase(&engine, "AAPL"), SessionPhase::Halted);
+    assert_eq!(trade_at(&mut engine, "GOOG", 1, 50.0), 0);
+    assert_eq!(phase(&engine, "GOOG"), SessionPhase::Halted);
+}
@@ -0,0 +1,117 @@
+mod support;
+
+use common::memory_storage::MemoryStorage;
+use common::models::{CancelRequest, DepthLevel, DepthMessage, DepthSnapshotRequest, DepthUpdate, Order, OrderKind, OrderType};
+use common::price::Price;
+use common::storage::Storage;
+use order_management_system::matching_engine::OrderEvents;
+use order_management_system::order_book_manager::OrderBookManager;
+use support::{engine, journal_path, order, settings};
+
+fn update(events: &OrderEvents) -> &DepthUpdate {
+    match events.depth.as_slice() {
+        [DepthMessage::Update(update)] => update,
+        depth => panic!("Expected one depth update, got {:?}", depth),
+    }
+}
+
+// Side, price, quantity and order count of every changed level
+fn changes(update: &DepthUpdate) -> Vec<(OrderType, f64, u32, u32)> {
+    update.changes.iter().map(|change| (change.side, change.price.to_f64(), change.quantity, change.order_count)).collect()
+}
+
+#[test]
+fn added_traded_and_cancelled_orders_update_their_levels() {
+    let mut engine = engine();
+
+    let events = engine.accept_order(order(1, OrderType::Sell, 10, 101.0), 0);
+    assert_eq!(changes(update(&events)), vec![(OrderType::Sell, 101.0, 10, 1)]);
+    assert_eq!(update(&events).sequence, 1);
+
+    let events = engine.accept_order(order(2, OrderType::Sell, 5, 101.0), 0);
+    assert_eq!(changes(update(&events)), vec![(OrderType::Sell, 101.0, 15, 2)]);
+
+    let events = engine.accept_order(order(3, OrderType::Buy, 20, 101.0), 0);
+    // The level is traded away and the rest of the buy order rests
+    assert_eq!(changes(update(&events)), vec![(OrderType::Buy, 101.0, 5, 1), (OrderType::Sell, 101.0, 0, 0)]);
+    assert_eq!(update(&events).sequence, 3);
+
+    let cancel = CancelRequest {
+        id: "cancel-3".to_string(),
+        order_id: "order-3".to_string(),
+        stock_symbol: "AAPL".to_string(),
+        timestamp: 0,
+    };
+    let events = engine.cancel_order(cancel, 0);
+    assert_eq!(changes(update(&events)), vec![(OrderType::Buy, 101.0, 0, 0)]);
+}
+
+#[test]
+fn order_that_changes_no_level_publishes_no_update() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 101.0), 0);
+
+    // A market sell with no bids to trade with, nothing rests
+    let events = engine.accept_order(Order { kind: OrderKind::Market, ..order(2, OrderType::Sell, 10, 0.0) }, 0);
+    assert!(events.depth.is_empty());
+}
+
+#[tokio::test]
+async fn snapshot_has_the_top_levels_or_is_empty_for_an_unknown_symbol() {
+    let path = journal_path("depth-snapshot");
+    let storage = MemoryStorage::new();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+    let manager = OrderBookManager::new(storage, &path, 0, settings()).await;
+    manager.process_order(order(1, OrderType::Buy, 10, 99.0)).await.unwrap();
+    manager.process_order(order(2, OrderType::Buy, 20, 99.0)).await.unwrap();
+
+    let events = manager.depth_snapshot(DepthSnapshotRequest { stock_symbol: "AAPL".to_string() }).await;
+    let [DepthMessage::Snapshot(snapshot)] = events.depth.as_slice() else {
+        panic!("Expected a depth snapshot");
+    };
+    let bids: Vec<(f64, u32, u32)> = snapshot.bids.iter().map(|level| (level.price.to_f64(), level.quantity, level.order_count)).collect();
+    assert_eq!(bids, vec![(99.0, 30, 2)]);
+    assert!(snapshot.asks.is_empty());
+    assert_eq!(snapshot.sequence, 2);
+
+    let events = manager.depth_snapshot(DepthSnapshotRequest { stock_symbol: "MSFT".to_string() }).await;
+    let [DepthMessage::Snapshot(snapshot)] = events.depth.as_slice() else {
+        panic!("Expected a depth snapshot");
+    };
+    assert_eq!(snapshot.stock_symbol, "MSFT");
+    assert!(snapshot.bids.is_empty() && snapshot.asks.is_empty());
+    assert_eq!(snapshot.sequence, 0);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn saved_order_books_can_be_read_by_their_top_levels() {
+    let path = journal_path("saved-depth");
+    let storage = MemoryStorage::new();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+    let manager = OrderBookManager::new(storage.clone(), &path, 0, settings()).await;
+    let orders = [(1, OrderType::Buy, 10, 99.0), (2, OrderType::Buy, 20, 99.0), (3, OrderType::Buy, 5, 98.0), (4, OrderType::Sell, 7, 101.0)];
+    for (sequence, order_type, quantity, price) in orders {
+        manager.process_order(order(sequence, order_type, quantity, price)).await.unwrap();
+    }
+    let stop = Order { kind: OrderKind::Stop, stop_price: Some(Price::from_f64(102.0)), ..order(5, OrderType::Buy, 10, 0.0) };
+    manager.process_order(stop).await.unwrap();
+
+    let levels = |levels: &[DepthLevel]| -> Vec<(f64, u32, u32)> {
+        levels.iter().map(|level| (level.price.to_f64(), level.quantity, level.order_count)).collect()
+    };
+    let (bids, asks) = storage.depth_levels("AAPL", 1).await.unwrap();
+    assert_eq!((levels(&bids), levels(&asks)), (vec![(99.0, 30, 2)], vec![(101.0, 7, 1)]));
+    let (bids, _) = storage.depth_levels("AAPL", 5).await.unwrap();
+    assert_eq!(levels(&bids), vec![(99.0, 30, 2), (98.0, 5, 1)]);
+    let (bids, asks) = storage.depth_levels("MSFT", 5).await.unwrap();
+    assert!(bids.is_empty() && asks.is_empty());
+
+    // The stop order is not resting in the book yet
+    let resting_orders = storage.resting_orders().await.unwrap();
+    let (buy_orders, sell_orders) = &resting_orders["AAPL"];
+    assert_eq!((buy_orders.len(), sell_orders.len()), (3, 1));
+
+    let _ = std::fs::remove_file(&path);
+}
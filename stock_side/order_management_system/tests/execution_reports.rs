@@ -0,0 +1,67 @@
+mod support;
+
+use common::models::{CancelRequest, ExecType, ExecutionReport, OrderType};
+use common::price::Price;
+use support::{engine, order};
+
+// Exec type, last quantity and price, cumulative and leaves quantity and average price of every report of the order
+fn reports_of(reports: &[ExecutionReport], order_id: &str) -> Vec<(ExecType, u32, f64, u32, u32, f64)> {
+    reports
+        .iter()
+        .filter(|report| report.order_id == order_id)
+        .map(|report| {
+            (
+                report.exec_type,
+                report.last_quantity,
+                report.last_price.to_f64(),
+                report.cumulative_quantity,
+                report.leaves_quantity,
+                report.average_price.to_f64(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn every_fill_reports_the_running_totals_and_average_price() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 100.0), 0);
+    engine.accept_order(order(2, OrderType::Sell, 10, 101.0), 0);
+    engine.accept_order(order(3, OrderType::Sell, 20, 102.5), 0);
+
+    let events = engine.accept_order(order(4, OrderType::Buy, 30, 103.0), 1);
+
+    assert_eq!(
+        reports_of(&events.execution_reports, "order-4"),
+        vec![
+            (ExecType::New, 0, 0.0, 0, 30, 0.0),
+            (ExecType::PartialFill, 10, 100.0, 10, 20, 100.0),
+            (ExecType::PartialFill, 10, 101.0, 20, 10, 100.5),
+            (ExecType::Fill, 10, 102.5, 30, 0, 101.1667),
+        ]
+    );
+    assert_eq!(reports_of(&events.execution_reports, "order-3"), vec![(ExecType::PartialFill, 10, 102.5, 10, 10, 102.5)]);
+}
+
+#[test]
+fn resting_order_keeps_its_totals_through_later_fills_and_a_cancel() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Buy, 30, 100.0), 0);
+
+    engine.accept_order(order(2, OrderType::Sell, 10, 100.0), 1);
+    let events = engine.accept_order(order(3, OrderType::Sell, 5, 99.0), 2);
+    // A resting order trades at its own price
+    assert_eq!(reports_of(&events.execution_reports, "order-1"), vec![(ExecType::PartialFill, 5, 100.0, 15, 15, 100.0)]);
+
+    let cancel = CancelRequest {
+        id: "cancel-1".to_string(),
+        order_id: "order-1".to_string(),
+        stock_symbol: "AAPL".to_string(),
+        timestamp: 3,
+    };
+    let events = engine.cancel_order(cancel, 3);
+    let report = &events.execution_reports[0];
+    assert_eq!((report.exec_type, report.cumulative_quantity, report.leaves_quantity), (ExecType::Cancelled, 15, 15));
+    assert_eq!(report.average_price, Price::from_f64(100.0));
+    assert!(report.reason.is_some());
+}
@@ -0,0 +1,68 @@
+mod support;
+
+use common::models::{Order, OrderType};
+use order_management_system::order_book::OrderBook;
+use support::order;
+
+fn iceberg(sequence: u64, order_type: OrderType, quantity: u32, display_quantity: u32, price: f64) -> Order {
+    Order { display_quantity: Some(display_quantity), ..order(sequence, order_type, quantity, price) }
+}
+
+fn fills(order_book: &mut OrderBook, incoming: Order) -> Vec<(String, u32)> {
+    let result = order_book.match_order(incoming);
+    result.trades.into_iter().map(|trade| (trade.sell_order_id, trade.quantity)).collect()
+}
+
+#[test]
+fn only_the_peak_is_shown_in_the_depth() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(iceberg(1, OrderType::Sell, 100, 20, 100.0));
+    order_book.match_order(order(2, OrderType::Sell, 30, 100.0));
+
+    let depth = order_book.depth_snapshot(10, 0);
+    assert_eq!(depth.asks[0].quantity, 50);
+    assert_eq!(depth.asks[0].order_count, 2);
+    assert_eq!(order_book.sell_orders()[0].visible_quantity, 20);
+}
+
+#[test]
+fn filled_peak_is_refreshed_from_the_hidden_quantity() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(iceberg(1, OrderType::Sell, 100, 20, 100.0));
+
+    // A peak at a time, the order is alone at its price so it trades again straight away
+    assert_eq!(fills(&mut order_book, order(2, OrderType::Buy, 30, 100.0)), vec![("order-1".to_string(), 20), ("order-1".to_string(), 10)]);
+
+    let resting = &order_book.sell_orders()[0];
+    assert_eq!(resting.quantity, 70);
+    assert_eq!(resting.visible_quantity, 10);
+    assert_eq!(order_book.depth_snapshot(10, 0).asks[0].quantity, 10);
+}
+
+#[test]
+fn refreshed_peak_goes_behind_the_orders_at_its_price() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(iceberg(1, OrderType::Sell, 100, 20, 100.0));
+    order_book.match_order(order(2, OrderType::Sell, 50, 100.0));
+
+    assert_eq!(fills(&mut order_book, order(3, OrderType::Buy, 30, 100.0)), vec![("order-1".to_string(), 20), ("order-2".to_string(), 10)]);
+
+    let sell_orders = order_book.sell_orders();
+    let queue: Vec<(&str, u32)> = sell_orders.iter().map(|order| (order.id.as_str(), order.quantity)).collect();
+    assert_eq!(queue, vec![("order-2", 40), ("order-1", 80)]);
+    // It keeps its sequence, only its place in the level changes
+    assert_eq!(sell_orders[1].sequence, 1);
+}
+
+#[test]
+fn last_peak_is_what_is_left() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(iceberg(1, OrderType::Sell, 50, 20, 100.0));
+
+    assert_eq!(fills(&mut order_book, order(2, OrderType::Buy, 40, 100.0)).len(), 2);
+    assert_eq!(order_book.sell_orders()[0].visible_quantity, 10);
+
+    assert_eq!(fills(&mut order_book, order(3, OrderType::Buy, 20, 100.0)), vec![("order-1".to_string(), 10)]);
+    assert!(order_book.sell_orders().is_empty());
+    assert_eq!(order_book.buy_orders()[0].quantity, 10);
+}
@@ -0,0 +1,204 @@
+mod support;
+
+use common::models::OrderType;
+use common::price::Price;
+use order_management_system::journal::{read_journal, Journal, JournalEvent};
+use order_management_system::matching_engine::MatchingEngine;
+use order_management_system::replay::replay_journal;
+use std::io::Write;
+use support::{engine, journal_path, named_order};
+
+// Journal a started engine and three orders the way the order book manager does, the last order makes two trades
+fn write_journal(path: &str) -> MatchingEngine {
+    let mut engine = engine();
+    let mut journal = Journal::open(path).unwrap();
+    let started = JournalEvent::Started {
+        settings: engine.settings().clone(),
+        next_sequence: engine.next_sequence(),
+        market_halted_until: 0,
+        order_books: Vec::new(),
+        restored: false,
+    };
+    journal.append(0, vec![started]).unwrap();
+
+    let orders = [
+        named_order("sell-1", OrderType::Sell, 50, 101.0),
+        named_order("sell-2", OrderType::Sell, 50, 100.0),
+        named_order("buy-1", OrderType::Buy, 80, 101.0),
+    ];
+    for (timestamp, order) in orders.into_iter().enumerate() {
+        let events = engine.accept_order(order.clone(), timestamp as u64);
+        let mut journal_events = vec![JournalEvent::NewOrder(order)];
+        journal_events.extend(events.trades.into_iter().map(JournalEvent::Trade));
+        journal.append(timestamp as u64, journal_events).unwrap();
+    }
+    engine
+}
+
+fn started(order_books: Vec<common::storage::SavedOrderBook>, restored: bool) -> JournalEvent {
+    JournalEvent::Started { settings: engine().settings().clone(), next_sequence: 10, market_halted_until: 0, order_books, restored }
+}
+
+#[test]
+fn replaying_the_journal_gives_the_same_trades() {
+    let path = journal_path("replay");
+    let orders = [
+        named_order("sell-1", OrderType::Sell, 50, 101.0),
+        named_order("sell-2", OrderType::Sell, 50, 100.0),
+        named_order("buy-1", OrderType::Buy, 80, 101.0),
+    ];
+
+    let mut live = engine();
+    let mut journal = Journal::open(&path).unwrap();
+    let mut live_trades = Vec::new();
+    for (timestamp, order) in orders.into_iter().enumerate() {
+        let events = live.accept_order(order.clone(), timestamp as u64);
+        let mut journal_events = vec![JournalEvent::NewOrder(order)];
+        journal_events.extend(events.trades.iter().cloned().map(JournalEvent::Trade));
+        journal.append(timestamp as u64, journal_events).unwrap();
+        live_trades.extend(events.trades);
+    }
+
+    let mut replayed = engine();
+    let mut journal_trades = Vec::new();
+    let mut replayed_trades = Vec::new();
+    for entry in read_journal(&path).unwrap() {
+        match entry.event {
+            JournalEvent::NewOrder(order) => replayed_trades.extend(replayed.accept_order(order, entry.timestamp).trades),
+            JournalEvent::Trade(trade) => journal_trades.push(trade),
+            event => panic!("Unexpected journal event {:?}", event),
+        }
+    }
+
+    let fills = |trades: &[common::models::Trade]| -> Vec<(String, String, u32, Price)> {
+        trades
+            .iter()
+            .map(|trade| (trade.buy_order_id.clone(), trade.sell_order_id.clone(), trade.quantity, trade.price))
+            .collect()
+    };
+    assert_eq!(live_trades.len(), 2);
+    assert_eq!(fills(&journal_trades), fills(&live_trades));
+    assert_eq!(fills(&replayed_trades), fills(&live_trades));
+    assert_eq!(replayed.last_sequence(), live.last_sequence());
+    assert_eq!(replayed.order_books()["AAPL"].sell_orders()[0].quantity, 20);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn an_incomplete_last_entry_is_dropped() {
+    let path = journal_path("torn");
+    let mut journal = Journal::open(&path).unwrap();
+    journal.append(1, vec![JournalEvent::Expire]).unwrap();
+    drop(journal);
+
+    // A crash in the middle of writing the second entry
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"{\"sequence\":2,\"timest").unwrap();
+    drop(file);
+
+    let mut journal = Journal::open(&path).unwrap();
+    journal.append(2, vec![JournalEvent::SessionPhase]).unwrap();
+
+    let sequences: Vec<u64> = read_journal(&path).unwrap().iter().map(|entry| entry.sequence).collect();
+    assert_eq!(sequences, vec![1, 2]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn replay_of_a_recorded_journal_has_no_mismatches() {
+    let path = journal_path("replay-clean");
+    let live = write_journal(&path);
+
+    let replay = replay_journal(&path).unwrap();
+
+    assert!(replay.mismatches.is_empty(), "{:?}", replay.mismatches);
+    assert_eq!(replay.events, 4);
+    assert_eq!(replay.trades, 2);
+    let replayed = replay.engine.unwrap();
+    assert_eq!(replayed.last_sequence(), live.last_sequence());
+    assert_eq!(replayed.order_books()["AAPL"].sell_orders()[0].quantity, 20);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn replay_reports_trades_that_differ_from_the_journal() {
+    let path = journal_path("replay-mismatch");
+    write_journal(&path);
+    let mut journal = Journal::open(&path).unwrap();
+
+    // A trade the replay doesn't make, and an order whose trade is missing from the journal
+    let mut trade = read_journal(&path)
+        .unwrap()
+        .into_iter()
+        .find_map(|entry| match entry.event {
+            JournalEvent::Trade(trade) => Some(trade),
+            _ => None,
+        })
+        .unwrap();
+    trade.quantity = 1;
+    journal.append(3, vec![JournalEvent::Trade(trade)]).unwrap();
+    journal.append(4, vec![JournalEvent::NewOrder(named_order("buy-2", OrderType::Buy, 10, 101.0))]).unwrap();
+
+    let replay = replay_journal(&path).unwrap();
+
+    assert_eq!(replay.mismatches.len(), 2, "{:?}", replay.mismatches);
+    assert!(replay.mismatches[0].contains("replay made no trade"));
+    assert!(replay.mismatches[1].contains("the journal doesn't have it"));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn replay_checks_the_order_books_loaded_at_a_restart_unless_they_were_restored() {
+    let path = journal_path("replay-restart");
+    write_journal(&path);
+    let mut journal = Journal::open(&path).unwrap();
+    journal.append(5, vec![started(Vec::new(), false)]).unwrap();
+    journal.append(6, vec![started(Vec::new(), true)]).unwrap();
+
+    let replay = replay_journal(&path).unwrap();
+
+    // Only the first restart claims to follow on from the replayed books, which still have sell-1
+    assert_eq!(replay.mismatches.len(), 1, "{:?}", replay.mismatches);
+    assert!(replay.mismatches[0].contains("differ from the replayed ones"));
+    assert!(replay.engine.unwrap().order_books().is_empty());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn replay_leaves_out_an_incomplete_last_entry() {
+    let path = journal_path("replay-torn");
+    write_journal(&path);
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"{\"sequence\":7,\"timestamp\":3,\"event\":{\"NewOrd").unwrap();
+    drop(file);
+
+    let replay = replay_journal(&path).unwrap();
+
+    assert!(replay.mismatches.is_empty(), "{:?}", replay.mismatches);
+    assert_eq!(replay.events, 4);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn broken_entry_in_the_middle_of_the_journal_is_an_error() {
+    let path = journal_path("broken");
+    let mut journal = Journal::open(&path).unwrap();
+    journal.append(1, vec![JournalEvent::Expire]).unwrap();
+    drop(journal);
+
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"not json\n{\"sequence\":2,\"timestamp\":2,\"event\":\"Expire\"}\n").unwrap();
+    drop(file);
+
+    assert_eq!(read_journal(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert!(Journal::open(&path).is_err());
+    assert!(replay_journal(&path).is_err());
+
+    let _ = std::fs::remove_file(&path);
+}
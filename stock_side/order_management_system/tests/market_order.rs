@@ -0,0 +1,59 @@
+mod support;
+
+use common::models::{ExecType, Order, OrderKind, OrderType};
+use order_management_system::matching_engine::MatchingEngine;
+use support::{engine, fills, order};
+
+// Sent without a price like a real market order, the price is ignored
+fn market_order(sequence: u64, order_type: OrderType, quantity: u32) -> Order {
+    Order { kind: OrderKind::Market, ..order(sequence, order_type, quantity, 0.0) }
+}
+
+// Sells 10 @ 100, 10 @ 101 and 10 @ 102
+fn engine_with_asks() -> MatchingEngine {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 100.0), 0);
+    engine.accept_order(order(2, OrderType::Sell, 10, 101.0), 0);
+    engine.accept_order(order(3, OrderType::Sell, 10, 102.0), 0);
+    engine
+}
+
+#[test]
+fn market_order_sweeps_the_levels_from_the_best_price() {
+    let mut engine = engine_with_asks();
+
+    let events = engine.accept_order(market_order(4, OrderType::Buy, 25), 1);
+
+    assert_eq!(fills(&events.trades), vec![("order-4", "order-1", 10, 100.0), ("order-4", "order-2", 10, 101.0), ("order-4", "order-3", 5, 102.0)]);
+    let sell_orders = engine.order_books()["AAPL"].sell_orders();
+    assert_eq!(sell_orders.len(), 1);
+    assert_eq!(sell_orders[0].quantity, 5);
+}
+
+#[test]
+fn unfilled_remainder_is_cancelled_instead_of_resting() {
+    let mut engine = engine_with_asks();
+
+    let events = engine.accept_order(market_order(4, OrderType::Buy, 40), 1);
+
+    assert_eq!(events.trades.iter().map(|trade| trade.quantity).sum::<u32>(), 30);
+    let last_report = events.execution_reports.last().unwrap();
+    assert_eq!(last_report.order_id, "order-4");
+    assert_eq!(last_report.exec_type, ExecType::Cancelled);
+    assert_eq!(last_report.cumulative_quantity, 30);
+    assert_eq!(last_report.leaves_quantity, 10);
+    assert!(engine.order_books()["AAPL"].buy_orders().is_empty());
+    assert!(engine.order_books()["AAPL"].sell_orders().is_empty());
+}
+
+#[test]
+fn market_order_against_an_empty_book_is_cancelled() {
+    let mut engine = engine();
+
+    let events = engine.accept_order(market_order(1, OrderType::Sell, 10), 0);
+
+    assert!(events.trades.is_empty());
+    let exec_types: Vec<ExecType> = events.execution_reports.iter().map(|report| report.exec_type).collect();
+    assert_eq!(exec_types, vec![ExecType::New, ExecType::Cancelled]);
+    assert!(engine.order_books()["AAPL"].sell_orders().is_empty());
+}
@@ -0,0 +1,292 @@
+mod support;
+
+use common::memory_storage::MemoryStorage;
+use common::models::{AckStatus, DepthLevel, Order, OrderKind, OrderType, ReferenceData, RejectReason, ReplaceRequest, Stock, TimeInForce, TrailingOffset};
+use common::price::Price;
+use common::storage::{MarketSnapshot, RestingOrders, SavedOrderBook, Storage, StorageError, StorageResult, SNAPSHOT_VERSION};
+use order_management_system::journal::{read_journal, JournalEvent};
+use order_management_system::order_book_manager::OrderBookManager;
+use order_management_system::replay::replay_journal;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use support::{journal_path, named_order, settings};
+
+#[tokio::test]
+async fn orders_are_matched_and_saved_without_redis() {
+    let path = journal_path("memory-storage");
+    let storage = MemoryStorage::new();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+
+    let manager = OrderBookManager::new(storage.clone(), &path, 0, settings()).await;
+    manager.process_order(named_order("sell-1", OrderType::Sell, 100, 100.0)).await.unwrap();
+    let events = manager.process_order(named_order("buy-1", OrderType::Buy, 60, 100.0)).await.unwrap();
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(events.trades[0].quantity, 60);
+
+    // The rest of the sell order is saved with the sequence number of the last order
+    let order_books = storage.order_books().await.unwrap();
+    assert_eq!(order_books.len(), 1);
+    assert!(order_books[0].buy_orders.is_empty());
+    assert_eq!(order_books[0].sell_orders.len(), 1);
+    assert_eq!(order_books[0].sell_orders[0].filled_quantity, 60);
+    assert_eq!(storage.sequence().await.unwrap(), Some(2));
+
+    // The id is remembered by the storage
+    let events = manager.process_order(named_order("buy-1", OrderType::Buy, 10, 100.0)).await.unwrap();
+    assert!(events.trades.is_empty());
+    assert_eq!(events.execution_reports[0].reject_reason, Some(RejectReason::DuplicateOrderId));
+
+    // A new manager on the same storage starts from the saved order book
+    drop(manager);
+    let manager = OrderBookManager::new(storage.clone(), &path, 0, settings()).await;
+    let events = manager.process_order(named_order("buy-2", OrderType::Buy, 40, 100.0)).await.unwrap();
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(events.trades[0].sell_order_id, "sell-1");
+    assert!(storage.order_books().await.unwrap()[0].sell_orders.is_empty());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn only_day_and_gtd_orders_expire() {
+    let path = journal_path("expire-time");
+    let storage = MemoryStorage::new();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+    let manager = OrderBookManager::new(storage.clone(), &path, 0, settings()).await;
+
+    let mut gtd_order = named_order("gtd-1", OrderType::Buy, 10, 99.0);
+    gtd_order.time_in_force = TimeInForce::GoodTillDate;
+    let events = manager.process_order(gtd_order).await.unwrap();
+    assert_eq!(events.execution_reports[0].reject_reason, Some(RejectReason::MissingExpireTime));
+
+    // Already in the past, but a GTC order doesn't expire so it is dropped
+    let mut gtc_order = named_order("gtc-1", OrderType::Buy, 10, 99.0);
+    gtc_order.expire_time = Some(1);
+    let events = manager.process_order(gtc_order).await.unwrap();
+    assert_eq!(events.execution_reports[0].reject_reason, None);
+    assert!(manager.expire_orders().await.expired.is_empty());
+    assert_eq!(storage.order_books().await.unwrap()[0].buy_orders[0].expire_time, None);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn a_replace_must_stay_near_the_stock_price() {
+    let path = journal_path("replace-price");
+    let storage = MemoryStorage::new();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+    let manager = OrderBookManager::new(storage.clone(), &path, 0, settings()).await;
+    manager.process_order(named_order("buy-1", OrderType::Buy, 10, 99.0)).await.unwrap();
+
+    let replace = |id: &str, price: f64| ReplaceRequest {
+        id: id.to_string(),
+        order_id: "buy-1".to_string(),
+        stock_symbol: "AAPL".to_string(),
+        quantity: 10,
+        price: Price::from_f64(price),
+        timestamp: 0,
+    };
+
+    let ack = manager.replace_order(replace("replace-1", 1000.0)).await.unwrap().ack.unwrap();
+    assert_eq!(ack.reject_reason, Some(RejectReason::PriceOutOfRange));
+    assert_eq!(storage.order_books().await.unwrap()[0].buy_orders[0].price, Price::from_f64(99.0));
+
+    let ack = manager.replace_order(replace("replace-2", 98.0)).await.unwrap().ack.unwrap();
+    assert_eq!(ack.status, AckStatus::Accepted);
+    assert_eq!(storage.order_books().await.unwrap()[0].buy_orders[0].price, Price::from_f64(98.0));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn ids_from_a_snapshot_are_duplicates() {
+    let path = journal_path("snapshot-ids");
+    let snapshot = MarketSnapshot {
+        version: SNAPSHOT_VERSION,
+        timestamp: 0,
+        prices: [("AAPL".to_string(), Price::from_f64(100.0))].into(),
+        sectors: Default::default(),
+        reference_data: Default::default(),
+        sequence: Some(1),
+        market_halted_until: 0,
+        order_books: vec![SavedOrderBook {
+            stock_symbol: "AAPL".to_string(),
+            sell_orders: vec![named_order("sell-1", OrderType::Sell, 10, 101.0)],
+            ..Default::default()
+        }],
+        order_ids: ["filled-1".to_string()].into(),
+    };
+    let manager = OrderBookManager::new(MemoryStorage::from_snapshot(snapshot), &path, 0, settings()).await;
+
+    for order_id in ["sell-1", "filled-1"] {
+        let events = manager.process_order(named_order(order_id, OrderType::Buy, 10, 99.0)).await.unwrap();
+        assert_eq!(events.execution_reports[0].reject_reason, Some(RejectReason::DuplicateOrderId));
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn the_journal_starts_over_after_a_restore() {
+    let path = journal_path("restored");
+    let storage = MemoryStorage::new();
+
+    // Only the first start after the storage was filled from outside
+    for _ in 0..2 {
+        drop(OrderBookManager::new(storage.clone(), &path, 0, settings()).await);
+    }
+    let restored: Vec<bool> = read_journal(&path)
+        .unwrap()
+        .into_iter()
+        .filter_map(|entry| match entry.event {
+            JournalEvent::Started { restored, .. } => Some(restored),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(restored, vec![true, false]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn last_prices_that_change_nothing_are_journaled_ahead_of_the_next_entry() {
+    let path = journal_path("last-price");
+    let storage = MemoryStorage::new();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+    let manager = OrderBookManager::new(storage.clone(), &path, 0, settings()).await;
+    manager.process_order(named_order("buy-1", OrderType::Buy, 10, 90.0)).await.unwrap();
+
+    let last_price = |price: f64| Stock { symbol: "AAPL".to_string(), price: Price::from_f64(price) };
+    for price in [101.0, 102.0, 103.0] {
+        manager.update_last_price(&last_price(price)).await;
+    }
+    let last_prices = |path: &str| -> Vec<Price> {
+        read_journal(path)
+            .unwrap()
+            .into_iter()
+            .filter_map(|entry| match entry.event {
+                JournalEvent::LastPrice(stock) => Some(stock.price),
+                _ => None,
+            })
+            .collect()
+    };
+    assert!(last_prices(&path).is_empty());
+
+    // The trailing stop starts from the last price, only the latest one is journaled before it
+    let trailing_stop = Order {
+        kind: OrderKind::TrailingStop,
+        trailing_offset: Some(TrailingOffset::Amount(Price::from_f64(2.0))),
+        ..named_order("stop-1", OrderType::Sell, 10, 0.0)
+    };
+    let events = manager.process_order(trailing_stop).await.unwrap();
+    assert_eq!(events.execution_reports[0].stop_price, Some(Price::from_f64(101.0)));
+    assert_eq!(last_prices(&path), vec![Price::from_f64(103.0)]);
+
+    // A price that moves the trailing stop is journaled right away
+    manager.update_last_price(&last_price(105.0)).await;
+    assert_eq!(last_prices(&path), vec![Price::from_f64(103.0), Price::from_f64(105.0)]);
+
+    let replay = replay_journal(&path).unwrap();
+    assert!(replay.mismatches.is_empty(), "{:?}", replay.mismatches);
+    let stop_orders = replay.engine.unwrap().order_books()["AAPL"].stop_orders();
+    assert_eq!(stop_orders[0].stop_price, Some(Price::from_f64(103.0)));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+// Memory storage whose saves fail while failing is set
+#[derive(Clone, Default)]
+struct FailingStorage {
+    storage: MemoryStorage,
+    failing: Arc<AtomicBool>,
+}
+
+impl Storage for FailingStorage {
+    async fn prices(&self) -> StorageResult<HashMap<String, Price>> {
+        self.storage.prices().await
+    }
+
+    async fn price(&self, stock_symbol: &str) -> StorageResult<Option<Price>> {
+        self.storage.price(stock_symbol).await
+    }
+
+    async fn set_price(&self, stock_symbol: &str, price: Price) -> StorageResult<()> {
+        self.storage.set_price(stock_symbol, price).await
+    }
+
+    async fn sectors(&self) -> StorageResult<HashMap<String, String>> {
+        self.storage.sectors().await
+    }
+
+    async fn reference_data(&self) -> StorageResult<HashMap<String, ReferenceData>> {
+        self.storage.reference_data().await
+    }
+
+    async fn order_books(&self) -> StorageResult<Vec<SavedOrderBook>> {
+        self.storage.order_books().await
+    }
+
+    async fn resting_orders(&self) -> StorageResult<RestingOrders> {
+        self.storage.resting_orders().await
+    }
+
+    async fn depth_levels(&self, stock_symbol: &str, levels: usize) -> StorageResult<(Vec<DepthLevel>, Vec<DepthLevel>)> {
+        self.storage.depth_levels(stock_symbol, levels).await
+    }
+
+    async fn load_order_books(&self) -> StorageResult<Vec<SavedOrderBook>> {
+        self.storage.load_order_books().await
+    }
+
+    async fn save_order_books(&self, order_books: Vec<SavedOrderBook>, sequence: u64, market_halted_until: u64) -> StorageResult<()> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(StorageError("Connection refused".to_string()));
+        }
+        self.storage.save_order_books(order_books, sequence, market_halted_until).await
+    }
+
+    async fn sequence(&self) -> StorageResult<Option<u64>> {
+        self.storage.sequence().await
+    }
+
+    async fn market_halted_until(&self) -> StorageResult<u64> {
+        self.storage.market_halted_until().await
+    }
+
+    async fn take_restored(&self) -> StorageResult<bool> {
+        self.storage.take_restored().await
+    }
+
+    async fn order_ids(&self) -> StorageResult<HashSet<String>> {
+        self.storage.order_ids().await
+    }
+
+    async fn add_order_id(&self, order_id: &str) -> StorageResult<bool> {
+        self.storage.add_order_id(order_id).await
+    }
+}
+
+#[tokio::test]
+async fn trades_are_published_and_the_book_saved_later_when_a_save_fails() {
+    let path = journal_path("failing-storage");
+    let storage = FailingStorage::default();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+
+    let manager = OrderBookManager::new(storage.clone(), &path, 0, settings()).await;
+    manager.process_order(named_order("sell-1", OrderType::Sell, 100, 100.0)).await.unwrap();
+
+    // The fill happened, so it is reported even though the book couldn't be saved
+    storage.failing.store(true, Ordering::SeqCst);
+    let events = manager.process_order(named_order("buy-1", OrderType::Buy, 60, 100.0)).await.unwrap();
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(events.execution_reports.iter().filter(|report| report.last_quantity == 60).count(), 2);
+    assert_eq!(storage.order_books().await.unwrap()[0].sell_orders[0].filled_quantity, 0);
+
+    // Nothing changes in the book, the next expiry check saves it again
+    storage.failing.store(false, Ordering::SeqCst);
+    manager.expire_orders().await;
+    assert_eq!(storage.order_books().await.unwrap()[0].sell_orders[0].filled_quantity, 60);
+
+    let _ = std::fs::remove_file(&path);
+}
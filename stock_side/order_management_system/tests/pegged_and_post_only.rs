@@ -0,0 +1,125 @@
+mod support;
+
+use common::models::{Order, OrderType, Peg, PegReference, PostOnly, RejectReason};
+use common::price::Price;
+use order_management_system::order_book::OrderBook;
+use order_management_system::validation::validate_order;
+use support::order;
+
+fn post_only(sequence: u64, order_type: OrderType, price: f64, post_only: PostOnly) -> Order {
+    Order { post_only: Some(post_only), ..order(sequence, order_type, 10, price) }
+}
+
+fn pegged(sequence: u64, order_type: OrderType, reference: PegReference, offset: f64) -> Order {
+    Order { peg: Some(Peg { reference, offset: Price::from_f64(offset) }), ..order(sequence, order_type, 10, 0.0) }
+}
+
+// Best bid 99, best offer 101
+fn order_book() -> OrderBook {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Buy, 10, 99.0));
+    order_book.match_order(order(2, OrderType::Sell, 10, 101.0));
+    order_book
+}
+
+#[test]
+fn post_only_order_that_would_trade_is_rejected() {
+    let mut order_book = order_book();
+
+    let result = order_book.match_order(post_only(3, OrderType::Buy, 101.0, PostOnly::Reject));
+
+    assert!(result.trades.is_empty());
+    assert_eq!(result.cancelled.map(|order| order.id), Some("order-3".to_string()));
+    assert_eq!(order_book.buy_orders().len(), 1);
+    assert_eq!(order_book.sell_orders()[0].quantity, 10);
+}
+
+#[test]
+fn post_only_order_that_would_trade_is_repriced_one_tick_behind() {
+    let mut order_book = order_book();
+
+    let result = order_book.match_order(post_only(3, OrderType::Sell, 98.0, PostOnly::Reprice));
+
+    assert!(result.trades.is_empty());
+    assert_eq!(result.repriced.map(|order| order.price), Some(Price::from_f64(99.01)));
+    assert_eq!(order_book.best_ask().map(|order| order.id.as_str()), Some("order-3"));
+}
+
+#[test]
+fn post_only_order_that_does_not_cross_rests_unchanged() {
+    let mut order_book = order_book();
+
+    let result = order_book.match_order(post_only(3, OrderType::Buy, 100.0, PostOnly::Reject));
+
+    assert!(result.cancelled.is_none());
+    assert!(result.repriced.is_none());
+    assert_eq!(order_book.best_bid().map(|order| order.price), Some(Price::from_f64(100.0)));
+}
+
+#[test]
+fn pegged_orders_take_the_price_of_their_reference() {
+    let mut order_book = order_book();
+
+    let result = order_book.match_order(pegged(3, OrderType::Buy, PegReference::BestBid, -0.01));
+    assert_eq!(result.repriced.map(|order| order.price), Some(Price::from_f64(98.99)));
+
+    // The midpoint of 99 and 101, rounded up to the tick for a sell
+    let result = order_book.match_order(pegged(4, OrderType::Sell, PegReference::Midpoint, 0.005));
+    assert_eq!(result.repriced.map(|order| order.price), Some(Price::from_f64(100.01)));
+    assert!(result.trades.is_empty());
+}
+
+#[test]
+fn pegged_order_follows_its_reference_and_loses_its_priority() {
+    let mut order_book = order_book();
+    order_book.match_order(pegged(3, OrderType::Buy, PegReference::BestBid, 0.0));
+    order_book.match_order(order(4, OrderType::Buy, 10, 99.5));
+
+    let results = order_book.reprice_pegged();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].repriced.as_ref().map(|order| order.price), Some(Price::from_f64(99.5)));
+    let bids: Vec<String> = order_book.buy_orders().into_iter().map(|order| order.id).collect();
+    assert_eq!(bids, vec!["order-4", "order-3", "order-1"]);
+}
+
+#[test]
+fn pegged_post_only_order_stays_one_tick_away_from_the_opposite_side() {
+    let mut order_book = order_book();
+
+    let result = order_book.match_order(Order { post_only: Some(PostOnly::Reprice), ..pegged(3, OrderType::Sell, PegReference::BestBid, 0.0) });
+
+    assert!(result.trades.is_empty());
+    assert_eq!(result.repriced.map(|order| order.price), Some(Price::from_f64(99.01)));
+}
+
+#[test]
+fn pegged_order_needs_a_valid_price_to_fall_back_to_and_a_bounded_offset() {
+    let stock_price = Some(Price::from_f64(100.0));
+    let validate = |price: f64, offset: f64| {
+        let order = Order { price: Price::from_f64(price), ..pegged(1, OrderType::Buy, PegReference::BestBid, offset) };
+        validate_order(&order, stock_price, None, 0)
+    };
+
+    assert_eq!(validate(99.0, -1.0), Ok(()));
+    assert_eq!(validate(0.0, -1.0), Err(RejectReason::InvalidPrice));
+    assert_eq!(validate(-5.0, -1.0), Err(RejectReason::InvalidPrice));
+    assert_eq!(validate(99.0, -50.01), Err(RejectReason::InvalidPegOffset));
+}
+
+#[test]
+fn pegged_order_keeps_its_price_when_its_reference_plus_offset_is_not_positive() {
+    let mut order_book = order_book();
+
+    for (sequence, offset) in [(3, Price::from_f64(-150.0)), (4, Price(i64::MAX))] {
+        let peg = Some(Peg { reference: PegReference::BestBid, offset });
+        let order = Order { peg, ..order(sequence, OrderType::Sell, 10, 102.0) };
+        let result = order_book.match_order(order);
+        assert!(result.trades.is_empty());
+        assert!(result.repriced.is_none());
+    }
+
+    let prices: Vec<Price> = order_book.sell_orders().iter().map(|order| order.price).collect();
+    assert_eq!(prices, vec![Price::from_f64(101.0), Price::from_f64(102.0), Price::from_f64(102.0)]);
+    assert!(order_book.reprice_pegged().is_empty());
+}
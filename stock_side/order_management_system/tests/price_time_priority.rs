@@ -0,0 +1,43 @@
+mod support;
+
+use common::models::OrderType;
+use order_management_system::order_book::OrderBook;
+use support::{engine, fills, order};
+
+#[test]
+fn better_price_fills_first_then_the_earlier_order() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Sell, 10, 101.0));
+    order_book.match_order(order(2, OrderType::Sell, 10, 100.0));
+    order_book.match_order(order(3, OrderType::Sell, 10, 100.0));
+
+    let result = order_book.match_order(order(4, OrderType::Buy, 25, 101.0));
+
+    assert_eq!(fills(&result.trades), vec![("order-4", "order-2", 10, 100.0), ("order-4", "order-3", 10, 100.0), ("order-4", "order-1", 5, 101.0)]);
+    assert_eq!(order_book.sell_orders()[0].id, "order-1");
+    assert_eq!(order_book.sell_orders()[0].quantity, 5);
+}
+
+#[test]
+fn partly_filled_order_keeps_its_place_at_the_front() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.match_order(order(1, OrderType::Buy, 10, 100.0));
+    order_book.match_order(order(2, OrderType::Buy, 10, 100.0));
+
+    let result = order_book.match_order(order(3, OrderType::Sell, 4, 100.0));
+    assert_eq!(fills(&result.trades), vec![("order-1", "order-3", 4, 100.0)]);
+
+    let result = order_book.match_order(order(4, OrderType::Sell, 10, 99.0));
+    assert_eq!(fills(&result.trades), vec![("order-1", "order-4", 6, 100.0), ("order-2", "order-4", 4, 100.0)]);
+}
+
+#[test]
+fn accepted_orders_get_increasing_sequence_numbers() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 100.0), 0);
+    engine.accept_order(order(2, OrderType::Sell, 10, 100.0), 0);
+
+    let sequences: Vec<u64> = engine.order_books()["AAPL"].sell_orders().iter().map(|order| order.sequence).collect();
+    assert_eq!(sequences, vec![1, 2]);
+    assert_eq!(engine.next_sequence(), 3);
+}
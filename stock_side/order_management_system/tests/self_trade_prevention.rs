@@ -0,0 +1,88 @@
+mod support;
+
+use common::models::{ExecType, Order, OrderType};
+use order_management_system::order_book::{OrderBook, SelfTradePrevention};
+
+fn order(sequence: u64, order_type: OrderType, quantity: u32, account_id: Option<&str>) -> Order {
+    Order { account_id: account_id.map(|account_id| account_id.to_string()), ..support::order(sequence, order_type, quantity, 100.0) }
+}
+
+// Sell 10 of account A first in the queue, then sell 10 of account B
+fn order_book(self_trade_prevention: SelfTradePrevention) -> OrderBook {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.set_self_trade_prevention(self_trade_prevention);
+    order_book.match_order(order(1, OrderType::Sell, 10, Some("A")));
+    order_book.match_order(order(2, OrderType::Sell, 10, Some("B")));
+    order_book
+}
+
+fn quantities(orders: Vec<Order>) -> Vec<(String, u32)> {
+    orders.into_iter().map(|order| (order.id, order.quantity)).collect()
+}
+
+#[test]
+fn cancel_newest_cancels_the_incoming_order() {
+    let mut order_book = order_book(SelfTradePrevention::CancelNewest);
+
+    let result = order_book.match_order(order(3, OrderType::Buy, 15, Some("A")));
+
+    assert!(result.trades.is_empty());
+    assert!(result.execution_reports.iter().any(|report| report.order_id == "order-3" && report.exec_type == ExecType::Cancelled));
+    assert!(order_book.buy_orders().is_empty());
+    assert_eq!(quantities(order_book.sell_orders()), vec![("order-1".to_string(), 10), ("order-2".to_string(), 10)]);
+}
+
+#[test]
+fn cancel_oldest_cancels_the_resting_order_and_keeps_matching() {
+    let mut order_book = order_book(SelfTradePrevention::CancelOldest);
+
+    let result = order_book.match_order(order(3, OrderType::Buy, 15, Some("A")));
+
+    let filled: Vec<(&str, u32)> = result.trades.iter().map(|trade| (trade.sell_order_id.as_str(), trade.quantity)).collect();
+    assert_eq!(filled, vec![("order-2", 10)]);
+    assert!(result.execution_reports.iter().any(|report| report.order_id == "order-1" && report.exec_type == ExecType::Cancelled));
+    assert!(order_book.sell_orders().is_empty());
+    assert_eq!(quantities(order_book.buy_orders()), vec![("order-3".to_string(), 5)]);
+}
+
+#[test]
+fn cancel_both_cancels_both_orders() {
+    let mut order_book = order_book(SelfTradePrevention::CancelBoth);
+
+    let result = order_book.match_order(order(3, OrderType::Buy, 15, Some("A")));
+
+    assert!(result.trades.is_empty());
+    assert!(order_book.buy_orders().is_empty());
+    assert_eq!(quantities(order_book.sell_orders()), vec![("order-2".to_string(), 10)]);
+}
+
+#[test]
+fn decrement_takes_the_smaller_quantity_off_both_orders() {
+    let mut order_book = order_book(SelfTradePrevention::Decrement);
+
+    let result = order_book.match_order(order(3, OrderType::Buy, 15, Some("A")));
+
+    // The resting order reaches 0 and is cancelled, the incoming order goes on with 5 and trades with account B
+    let filled: Vec<(&str, u32)> = result.trades.iter().map(|trade| (trade.sell_order_id.as_str(), trade.quantity)).collect();
+    assert_eq!(filled, vec![("order-2", 5)]);
+    let exec_types: Vec<(&str, ExecType)> = result
+        .execution_reports
+        .iter()
+        .filter(|report| report.reason.is_some())
+        .map(|report| (report.order_id.as_str(), report.exec_type))
+        .collect();
+    assert_eq!(exec_types, vec![("order-3", ExecType::Restated), ("order-1", ExecType::Cancelled)]);
+    assert!(order_book.buy_orders().is_empty());
+    assert_eq!(quantities(order_book.sell_orders()), vec![("order-2".to_string(), 5)]);
+}
+
+#[test]
+fn orders_without_an_account_always_trade() {
+    let mut order_book = OrderBook::new("AAPL");
+    order_book.set_self_trade_prevention(SelfTradePrevention::CancelBoth);
+    order_book.match_order(order(1, OrderType::Sell, 10, None));
+
+    let result = order_book.match_order(order(2, OrderType::Buy, 10, None));
+
+    assert_eq!(result.trades.len(), 1);
+}
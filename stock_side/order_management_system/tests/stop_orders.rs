@@ -0,0 +1,95 @@
+mod support;
+
+use common::models::{ExecType, Order, OrderKind, OrderType, Stock};
+use common::price::Price;
+use order_management_system::matching_engine::{MatchingEngine, OrderEvents};
+use support::{engine, fills, order};
+
+fn stop(sequence: u64, order_type: OrderType, quantity: u32, stop_price: f64) -> Order {
+    Order { kind: OrderKind::Stop, stop_price: Some(Price::from_f64(stop_price)), ..order(sequence, order_type, quantity, 0.0) }
+}
+
+fn stop_limit(sequence: u64, order_type: OrderType, quantity: u32, stop_price: f64, price: f64) -> Order {
+    Order { kind: OrderKind::StopLimit, stop_price: Some(Price::from_f64(stop_price)), ..order(sequence, order_type, quantity, price) }
+}
+
+fn last_price(engine: &mut MatchingEngine, price: f64) -> OrderEvents {
+    engine.update_last_price(&Stock { symbol: "AAPL".to_string(), price: Price::from_f64(price) }, 0)
+}
+
+fn triggered(events: &OrderEvents) -> Vec<&str> {
+    events
+        .execution_reports
+        .iter()
+        .filter(|report| report.exec_type == ExecType::Triggered)
+        .map(|report| report.order_id.as_str())
+        .collect()
+}
+
+#[test]
+fn buy_stop_triggers_when_the_last_price_rises_to_its_stop_price() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 105.0), 0);
+    let events = engine.accept_order(stop(2, OrderType::Buy, 10, 104.0), 0);
+    assert!(events.trades.is_empty());
+    assert_eq!(engine.order_books()["AAPL"].stop_orders().len(), 1);
+
+    assert!(triggered(&last_price(&mut engine, 103.99)).is_empty());
+
+    let events = last_price(&mut engine, 104.0);
+    assert_eq!(triggered(&events), vec!["order-2"]);
+    // Triggered as a market order
+    assert_eq!(fills(&events.trades), vec![("order-2", "order-1", 10, 105.0)]);
+    assert!(engine.order_books()["AAPL"].stop_orders().is_empty());
+}
+
+#[test]
+fn sell_stop_limit_triggers_as_a_limit_order_and_rests_its_remainder() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Buy, 10, 95.0), 0);
+    engine.accept_order(order(2, OrderType::Buy, 10, 94.0), 0);
+    engine.accept_order(stop_limit(3, OrderType::Sell, 15, 96.0, 95.0), 0);
+
+    assert!(triggered(&last_price(&mut engine, 96.01)).is_empty());
+
+    let events = last_price(&mut engine, 96.0);
+    assert_eq!(triggered(&events), vec!["order-3"]);
+    assert_eq!(fills(&events.trades), vec![("order-1", "order-3", 10, 95.0)]);
+    let sell_orders = engine.order_books()["AAPL"].sell_orders();
+    assert_eq!(sell_orders.len(), 1);
+    assert_eq!((sell_orders[0].id.as_str(), sell_orders[0].kind, sell_orders[0].quantity), ("order-3", OrderKind::Limit, 5));
+}
+
+#[test]
+fn trade_of_a_triggered_stop_triggers_the_next_one() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Buy, 10, 99.0), 0);
+    engine.accept_order(order(2, OrderType::Buy, 10, 98.0), 0);
+    engine.accept_order(order(3, OrderType::Buy, 10, 97.0), 0);
+    engine.accept_order(stop(4, OrderType::Sell, 10, 98.0), 0);
+    engine.accept_order(stop(5, OrderType::Sell, 10, 99.0), 0);
+
+    // Trading at 99 triggers the stop at 99, its trade at 98 triggers the stop at 98
+    let events = engine.accept_order(order(6, OrderType::Sell, 10, 99.0), 1);
+
+    assert_eq!(triggered(&events), vec!["order-5", "order-4"]);
+    assert_eq!(
+        fills(&events.trades),
+        vec![("order-1", "order-6", 10, 99.0), ("order-2", "order-5", 10, 98.0), ("order-3", "order-4", 10, 97.0)]
+    );
+    assert!(engine.order_books()["AAPL"].stop_orders().is_empty());
+}
+
+#[test]
+fn stops_reached_together_trigger_in_the_order_the_price_reaches_them() {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 30, 110.0), 0);
+    engine.accept_order(stop(2, OrderType::Buy, 10, 102.0), 0);
+    engine.accept_order(stop(3, OrderType::Buy, 10, 101.0), 0);
+    engine.accept_order(stop(4, OrderType::Buy, 10, 101.0), 0);
+
+    let events = last_price(&mut engine, 103.0);
+
+    // Lowest stop price first for buy stops, then sequence
+    assert_eq!(triggered(&events), vec!["order-3", "order-4", "order-2"]);
+}
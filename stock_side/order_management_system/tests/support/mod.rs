@@ -0,0 +1,69 @@
+// Fixtures shared by the integration tests, every test file uses only some of them
+#![allow(dead_code)]
+
+use common::models::{Order, OrderKind, OrderType, TimeInForce, Trade};
+use common::price::Price;
+use order_management_system::matching_engine::{CircuitBreakers, EngineSettings, MatchingEngine};
+use order_management_system::order_book::SelfTradePrevention;
+use std::collections::{BTreeMap, HashMap};
+
+// GTC limit order for AAPL that may fill partly
+pub fn named_order(id: &str, order_type: OrderType, quantity: u32, price: f64) -> Order {
+    Order {
+        id: id.to_string(),
+        stock_symbol: "AAPL".to_string(),
+        order_type,
+        account_id: None,
+        kind: OrderKind::Limit,
+        quantity,
+        price: Price::from_f64(price),
+        stop_price: None,
+        trailing_offset: None,
+        post_only: None,
+        peg: None,
+        display_quantity: None,
+        timestamp: 0,
+        partial_fill: true,
+        time_in_force: TimeInForce::GoodTillCancel,
+        expire_time: None,
+        sequence: 0,
+        filled_quantity: 0,
+        average_price: Price::ZERO,
+        visible_quantity: 0,
+    }
+}
+
+// Same as named_order, with id order-<sequence> and that sequence already assigned
+pub fn order(sequence: u64, order_type: OrderType, quantity: u32, price: f64) -> Order {
+    Order { sequence, ..named_order(&format!("order-{}", sequence), order_type, quantity, price) }
+}
+
+// No reference data, call auctions or trading hours, circuit breakers far enough away not to trip
+pub fn settings() -> EngineSettings {
+    EngineSettings {
+        self_trade_prevention: SelfTradePrevention::CancelNewest,
+        reference_data: HashMap::new(),
+        call_auctions: Vec::new(),
+        trading_hours: None,
+        circuit_breakers: CircuitBreakers { price_band: 0.5, market_wide_move: 0.5, cool_down: 300 },
+    }
+}
+
+pub fn engine() -> MatchingEngine {
+    MatchingEngine::new(BTreeMap::new(), 1, 0, settings(), 0)
+}
+
+// Every trade as buy order id, sell order id, quantity and price
+pub fn fills(trades: &[Trade]) -> Vec<(&str, &str, u32, f64)> {
+    trades
+        .iter()
+        .map(|trade| (trade.buy_order_id.as_str(), trade.sell_order_id.as_str(), trade.quantity, trade.price.to_f64()))
+        .collect()
+}
+
+// Journal file of the test in the temp directory, removed if an earlier run left it behind
+pub fn journal_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("oms-journal-{}-{}.jsonl", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path.to_string_lossy().into_owned()
+}
@@ -0,0 +1,60 @@
+mod support;
+
+use common::models::{ExecType, Order, OrderType, TimeInForce};
+use order_management_system::matching_engine::MatchingEngine;
+use support::{engine, fills, order};
+
+fn with_time_in_force(time_in_force: TimeInForce, order: Order) -> Order {
+    Order { time_in_force, ..order }
+}
+
+// Sells 10 @ 100 and 10 @ 101
+fn engine_with_asks() -> MatchingEngine {
+    let mut engine = engine();
+    engine.accept_order(order(1, OrderType::Sell, 10, 100.0), 0);
+    engine.accept_order(order(2, OrderType::Sell, 10, 101.0), 0);
+    engine
+}
+
+fn sell_quantities(engine: &MatchingEngine) -> Vec<(String, u32)> {
+    engine.order_books()["AAPL"].sell_orders().into_iter().map(|order| (order.id, order.quantity)).collect()
+}
+
+#[test]
+fn immediate_or_cancel_fills_what_it_can_and_cancels_the_rest() {
+    let mut engine = engine_with_asks();
+
+    let events = engine.accept_order(with_time_in_force(TimeInForce::ImmediateOrCancel, order(3, OrderType::Buy, 15, 100.0)), 1);
+
+    assert_eq!(fills(&events.trades), vec![("order-3", "order-1", 10, 100.0)]);
+    let last_report = events.execution_reports.last().unwrap();
+    assert_eq!(last_report.order_id, "order-3");
+    assert_eq!(last_report.exec_type, ExecType::Cancelled);
+    assert_eq!(last_report.leaves_quantity, 5);
+    assert!(engine.order_books()["AAPL"].buy_orders().is_empty());
+}
+
+#[test]
+fn fill_or_kill_that_cannot_fill_completely_does_nothing() {
+    let mut engine = engine_with_asks();
+
+    let events = engine.accept_order(with_time_in_force(TimeInForce::FillOrKill, order(3, OrderType::Buy, 25, 101.0)), 1);
+
+    assert!(events.trades.is_empty());
+    let exec_types: Vec<ExecType> = events.execution_reports.iter().map(|report| report.exec_type).collect();
+    assert_eq!(exec_types, vec![ExecType::New, ExecType::Cancelled]);
+    assert!(engine.order_books()["AAPL"].buy_orders().is_empty());
+    assert_eq!(sell_quantities(&engine), vec![("order-1".to_string(), 10), ("order-2".to_string(), 10)]);
+}
+
+#[test]
+fn fill_or_kill_fills_completely_across_levels() {
+    let mut engine = engine_with_asks();
+
+    let events = engine.accept_order(with_time_in_force(TimeInForce::FillOrKill, order(3, OrderType::Buy, 15, 101.0)), 1);
+
+    assert_eq!(fills(&events.trades), vec![("order-3", "order-1", 10, 100.0), ("order-3", "order-2", 5, 101.0)]);
+    let last_report = events.execution_reports.iter().rfind(|report| report.order_id == "order-3").unwrap();
+    assert_eq!(last_report.exec_type, ExecType::Fill);
+    assert_eq!(sell_quantities(&engine), vec![("order-2".to_string(), 5)]);
+}
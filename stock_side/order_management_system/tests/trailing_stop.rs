@@ -0,0 +1,97 @@
+mod support;
+
+use common::models::{ExecType, Order, OrderKind, OrderType, RejectReason, Stock, TrailingOffset};
+use common::price::Price;
+use order_management_system::matching_engine::{MatchingEngine, OrderEvents};
+use order_management_system::validation::validate_order;
+use support::{engine, named_order};
+
+fn trailing_stop(id: &str, order_type: OrderType, quantity: u32, trailing_offset: TrailingOffset) -> Order {
+    Order { kind: OrderKind::TrailingStop, trailing_offset: Some(trailing_offset), ..named_order(id, order_type, quantity, 0.0) }
+}
+
+fn last_price(engine: &mut MatchingEngine, price: f64) -> OrderEvents {
+    engine.update_last_price(&Stock { symbol: "AAPL".to_string(), price: Price::from_f64(price) }, 0)
+}
+
+fn stop_price(engine: &MatchingEngine) -> Option<Price> {
+    engine.order_books()["AAPL"].stop_orders().first().and_then(|order| order.stop_price)
+}
+
+#[test]
+fn sell_trailing_stop_follows_the_price_up_and_triggers_on_the_way_down() {
+    let mut engine = engine();
+    engine.accept_order(named_order("buy-1", OrderType::Buy, 10, 90.0), 0);
+    last_price(&mut engine, 100.0);
+
+    let mut stop = trailing_stop("stop-1", OrderType::Sell, 10, TrailingOffset::Amount(Price::from_f64(2.0)));
+    engine.prepare_order(&mut stop, 0).unwrap();
+    assert_eq!(stop.stop_price, Some(Price::from_f64(98.0)));
+    engine.accept_order(stop, 0);
+
+    let events = last_price(&mut engine, 105.0);
+    assert_eq!(stop_price(&engine), Some(Price::from_f64(103.0)));
+    assert!(events.execution_reports.iter().any(|report| report.order_id == "stop-1" && report.exec_type == ExecType::Restated));
+
+    // It never moves away from the market
+    let events = last_price(&mut engine, 104.0);
+    assert_eq!(stop_price(&engine), Some(Price::from_f64(103.0)));
+    assert!(events.execution_reports.is_empty());
+
+    let events = last_price(&mut engine, 103.0);
+    assert!(events.execution_reports.iter().any(|report| report.order_id == "stop-1" && report.exec_type == ExecType::Triggered));
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(events.trades[0].sell_order_id, "stop-1");
+    assert_eq!(events.trades[0].price, Price::from_f64(90.0));
+    assert!(engine.order_books()["AAPL"].stop_orders().is_empty());
+}
+
+#[test]
+fn buy_trailing_stop_in_percent_follows_the_price_down() {
+    let mut engine = engine();
+    engine.accept_order(named_order("sell-1", OrderType::Sell, 10, 120.0), 0);
+    last_price(&mut engine, 100.0);
+
+    let mut stop = trailing_stop("stop-1", OrderType::Buy, 10, TrailingOffset::Percent(10.0));
+    engine.prepare_order(&mut stop, 0).unwrap();
+    engine.accept_order(stop, 0);
+    assert_eq!(stop_price(&engine), Some(Price::from_f64(110.0)));
+
+    last_price(&mut engine, 90.0);
+    assert_eq!(stop_price(&engine), Some(Price::from_f64(99.0)));
+    last_price(&mut engine, 95.0);
+    assert_eq!(stop_price(&engine), Some(Price::from_f64(99.0)));
+
+    let events = last_price(&mut engine, 99.0);
+    assert_eq!(events.trades.len(), 1);
+    assert_eq!(events.trades[0].buy_order_id, "stop-1");
+    assert_eq!(events.trades[0].price, Price::from_f64(120.0));
+}
+
+#[test]
+fn offsets_further_than_a_stop_price_may_be_are_refused() {
+    let stock_price = Some(Price::from_f64(100.0));
+    let validate = |order_type, trailing_offset| validate_order(&trailing_stop("stop-1", order_type, 10, trailing_offset), stock_price, None, 0);
+
+    assert_eq!(validate(OrderType::Sell, TrailingOffset::Amount(Price::from_f64(50.0))), Ok(()));
+    assert_eq!(validate(OrderType::Sell, TrailingOffset::Amount(Price::from_f64(50.01))), Err(RejectReason::InvalidTrailingOffset));
+    assert_eq!(validate(OrderType::Buy, TrailingOffset::Amount(Price(i64::MAX - 10))), Err(RejectReason::InvalidTrailingOffset));
+    assert_eq!(validate(OrderType::Sell, TrailingOffset::Percent(50.0)), Ok(()));
+    assert_eq!(validate(OrderType::Sell, TrailingOffset::Percent(100.0)), Err(RejectReason::InvalidTrailingOffset));
+    assert_eq!(validate(OrderType::Sell, TrailingOffset::Percent(f64::NAN)), Err(RejectReason::InvalidTrailingOffset));
+}
+
+#[test]
+fn trailing_stop_without_a_positive_level_is_refused_instead_of_overflowing() {
+    let mut engine = engine();
+    engine.accept_order(named_order("buy-1", OrderType::Buy, 10, 90.0), 0);
+    last_price(&mut engine, 100.0);
+
+    let mut stop = trailing_stop("stop-1", OrderType::Buy, 10, TrailingOffset::Amount(Price(i64::MAX - 10)));
+    assert_eq!(engine.prepare_order(&mut stop, 0), Err(RejectReason::InvalidTrailingOffset));
+
+    // The last price fell since the stock price the offset was checked against
+    let mut stop = trailing_stop("stop-2", OrderType::Sell, 10, TrailingOffset::Amount(Price::from_f64(100.0)));
+    assert_eq!(engine.prepare_order(&mut stop, 0), Err(RejectReason::InvalidTrailingOffset));
+    assert!(engine.order_books()["AAPL"].stop_orders().is_empty());
+}
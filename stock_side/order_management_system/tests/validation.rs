@@ -0,0 +1,98 @@
+mod support;
+
+use common::memory_storage::MemoryStorage;
+use common::models::{ExecType, Order, OrderKind, OrderType, ReferenceData, RejectReason};
+use common::price::Price;
+use common::storage::Storage;
+use order_management_system::order_book_manager::OrderBookManager;
+use support::{journal_path, named_order, settings};
+
+// Reject reason of every order, in the order they were sent, and whether any of them reached the order book
+async fn rejects(name: &str, storage: MemoryStorage, orders: Vec<Order>) -> (Vec<Option<RejectReason>>, bool) {
+    let path = journal_path(name);
+    let manager = OrderBookManager::new(storage.clone(), &path, 0, settings()).await;
+
+    let mut reject_reasons = Vec::new();
+    for order in orders {
+        let events = manager.process_order(order).await.unwrap();
+        let report = &events.execution_reports[0];
+        assert_eq!(report.exec_type == ExecType::Rejected, report.reject_reason.is_some());
+        reject_reasons.push(report.reject_reason);
+    }
+    let reached_the_book = !storage.order_books().await.unwrap().is_empty();
+
+    let _ = std::fs::remove_file(&path);
+    (reject_reasons, reached_the_book)
+}
+
+#[tokio::test]
+async fn invalid_new_orders_are_rejected_before_the_order_book() {
+    let storage = MemoryStorage::new();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+
+    let orders = vec![
+        named_order("zero-quantity", OrderType::Buy, 0, 99.0),
+        named_order("zero-price", OrderType::Buy, 10, 0.0),
+        named_order("negative-price", OrderType::Sell, 10, -1.0),
+        Order { stock_symbol: "MSFT".to_string(), ..named_order("unknown-symbol", OrderType::Buy, 10, 99.0) },
+        // 50% either side of the stock price of 100
+        named_order("too-high", OrderType::Buy, 10, 150.01),
+        named_order("too-low", OrderType::Sell, 10, 49.99),
+    ];
+    let (reject_reasons, reached_the_book) = rejects("new-order-rejects", storage, orders).await;
+
+    assert_eq!(
+        reject_reasons,
+        vec![
+            Some(RejectReason::ZeroQuantity),
+            Some(RejectReason::InvalidPrice),
+            Some(RejectReason::InvalidPrice),
+            Some(RejectReason::UnknownSymbol),
+            Some(RejectReason::PriceOutOfRange),
+            Some(RejectReason::PriceOutOfRange),
+        ]
+    );
+    assert!(!reached_the_book);
+}
+
+#[tokio::test]
+async fn prices_at_the_edge_of_the_range_are_accepted() {
+    let storage = MemoryStorage::new();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+
+    let orders = vec![named_order("buy-1", OrderType::Buy, 10, 50.0), named_order("sell-1", OrderType::Sell, 10, 150.0)];
+    let (reject_reasons, reached_the_book) = rejects("price-range-edges", storage, orders).await;
+
+    assert_eq!(reject_reasons, vec![None, None]);
+    assert!(reached_the_book);
+}
+
+#[tokio::test]
+async fn orders_must_fit_the_reference_data_of_their_stock() {
+    let storage = MemoryStorage::new();
+    storage.set_price("AAPL", Price::from_f64(100.0)).await.unwrap();
+    storage.set_reference_data("AAPL", ReferenceData { tick_size: Price::from_f64(0.05), lot_size: 10, min_quantity: 20, price_band: None });
+
+    let orders = vec![
+        named_order("off-tick", OrderType::Buy, 20, 99.01),
+        Order { kind: OrderKind::StopLimit, stop_price: Some(Price::from_f64(101.02)), ..named_order("stop-off-tick", OrderType::Buy, 20, 101.05) },
+        named_order("odd-lot", OrderType::Buy, 25, 99.0),
+        Order { display_quantity: Some(15), ..named_order("odd-lot-peak", OrderType::Buy, 30, 99.0) },
+        named_order("below-minimum", OrderType::Buy, 10, 99.0),
+        named_order("fits", OrderType::Buy, 30, 99.05),
+    ];
+    let (reject_reasons, reached_the_book) = rejects("reference-data-rejects", storage, orders).await;
+
+    assert_eq!(
+        reject_reasons,
+        vec![
+            Some(RejectReason::InvalidTickSize),
+            Some(RejectReason::InvalidTickSize),
+            Some(RejectReason::InvalidLotSize),
+            Some(RejectReason::InvalidLotSize),
+            Some(RejectReason::BelowMinimumQuantity),
+            None,
+        ]
+    );
+    assert!(reached_the_book);
+}
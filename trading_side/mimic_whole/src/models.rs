@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Order {
@@ -17,8 +17,33 @@ pub enum OrderType {
     Sell,
 }
 
+// Trading rules of a stock from stocks:tick_size, stocks:lot_size and stocks:min_quantity, generated orders follow them
+#[derive(Debug, Clone, Copy)]
+pub struct ReferenceData {
+    pub tick_size: f64,
+    pub lot_size: u32,
+    pub min_quantity: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Stock {
     pub symbol: String,
+    // The stock side sends prices as decimal strings ("113.0100"), kept as a number here so the web page can still use it as one
+    #[serde(deserialize_with = "deserialize_price")]
     pub price: f64,
+}
+
+// Accept a price as a decimal string or as a plain JSON number
+fn deserialize_price<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(f64),
+    }
+
+    match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(price) => price.parse().map_err(de::Error::custom),
+        StringOrNumber::Number(price) => Ok(price),
+    }
 }
\ No newline at end of file
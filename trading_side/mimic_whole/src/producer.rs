@@ -1,4 +1,4 @@
-use crate::models::{Order, OrderType};
+use crate::models::{Order, OrderType, ReferenceData};
 
 use redis::Commands;
 use rand::rngs::StdRng;
@@ -10,6 +10,7 @@ use tokio::time::{Duration, sleep};
 use rdkafka::config::ClientConfig;
 use rdkafka::producer::{FutureProducer, FutureRecord};
 use rdkafka::util::Timeout;
+use std::collections::HashMap;
 use std::time::Duration as StdDuration;
 
 #[derive(Clone)]
@@ -39,6 +40,22 @@ impl OrderProducer {
 
         let symbol_price: Vec<(String, f64)> = stock_symbols_string.iter().map(|(k, v)| (k.clone(), v.parse::<f64>().unwrap())).collect();
 
+        // Stocks without reference data get orders that are valid for any lot size of 1 and tick size of 0.01
+        let tick_sizes: HashMap<String, f64> = con.hgetall("stocks:tick_size").unwrap();
+        let lot_sizes: HashMap<String, u32> = con.hgetall("stocks:lot_size").unwrap();
+        let min_quantities: HashMap<String, u32> = con.hgetall("stocks:min_quantity").unwrap();
+        let reference_data: HashMap<String, ReferenceData> = symbol_price
+            .iter()
+            .map(|(symbol, _)| {
+                let reference_data = ReferenceData {
+                    tick_size: tick_sizes.get(symbol).copied().unwrap_or(0.01),
+                    lot_size: lot_sizes.get(symbol).copied().unwrap_or(1),
+                    min_quantity: min_quantities.get(symbol).copied().unwrap_or(1),
+                };
+                (symbol.clone(), reference_data)
+            })
+            .collect();
+
 
         let producer = self.producer.clone();
         let topic = self.topic.clone();
@@ -48,7 +65,8 @@ impl OrderProducer {
                 let seed: [u8; 32] = rand::random();
                 let mut rng = StdRng::from_seed(seed);
                 loop {
-                    let order = generate_random_order(&symbol_price[rng.gen_range(0..symbol_price.len())]);
+                    let symbol_price = &symbol_price[rng.gen_range(0..symbol_price.len())];
+                    let order = generate_random_order(symbol_price, &reference_data[&symbol_price.0]);
                     println!("Generated order: {:?}", order);
                     send_message(&producer, &topic, order).await;
 
@@ -113,20 +131,35 @@ async fn send_message(producer: &FutureProducer, topic: &str, message: Order) {
         .unwrap();
 }
 
-fn generate_random_order(symbol_price: &(String, f64)) -> Order {
+fn generate_random_order(symbol_price: &(String, f64), reference_data: &ReferenceData) -> Order {
     // let stock_symbols = vec!["AAPL", "GOOGL", "AMZN", "MSFT", "TSLA"];
 
-    let order_types = vec![OrderType::Buy, OrderType::Sell];
+    let order_types = [OrderType::Buy, OrderType::Sell];
     let mut rng = rand::thread_rng();
 
     Order {
         id: Uuid::new_v4().to_string(),
         stock_symbol: symbol_price.0.clone(),
         order_type: order_types[rng.gen_range(0..order_types.len())].clone(),
-        quantity: rng.gen_range(5..150),
-        price: symbol_price.1.clone() * (1.0 + (rng.gen_range(-15..15) as f64 / 100.0)),    // Random price between -15% and +15% of the current price
+        quantity: random_quantity(&mut rng, reference_data),
+        price: random_price(&mut rng, symbol_price.1, reference_data),
         timestamp: Utc::now().timestamp() as u64,
         partial_fill: true,
         // TODO: limit_order: research possibilities
     }
+}
+
+// Random quantity between 5 and 150, rounded up to the lot size and at least the minimum quantity
+fn random_quantity(rng: &mut impl Rng, reference_data: &ReferenceData) -> u32 {
+    let lot_size = reference_data.lot_size.max(1);
+    let quantity = rng.gen_range(5..150).max(reference_data.min_quantity);
+    quantity.div_ceil(lot_size) * lot_size
+}
+
+// Random price between -15% and +15% of the current price, rounded to the tick size
+fn random_price(rng: &mut impl Rng, current_price: f64, reference_data: &ReferenceData) -> f64 {
+    let price = current_price * (1.0 + (rng.gen_range(-15..15) as f64 / 100.0));
+    let ticks = (price / reference_data.tick_size).round().max(1.0);
+    // Round again to get rid of the floating point noise of the multiplication, e.g. 113.00000000001
+    (ticks * reference_data.tick_size * 1e8).round() / 1e8
 }
\ No newline at end of file